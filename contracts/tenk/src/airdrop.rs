@@ -4,8 +4,14 @@ use raffle_collection::RaffleCollection;
 
 #[near_bindgen]
 impl Contract {
-    pub fn initialize_airdop(&self, total_supply: u32, max_winners: u32) {
+    /// Start a raffle among the first `total_supply` minted tokens, with at most `max_winners`
+    /// @allow ["::owner"]
+    pub fn initialize_airdrop(&mut self, total_supply: u32, max_winners: u32) {
         self.assert_owner();
+        require!(
+            total_supply as u64 <= self.minted_tokens.len(),
+            "Can't raffle more tokens than have been minted"
+        );
         initialize_raffle_collection(
             StorageKey::AirdropLazyKey,
             StorageKey::AirdropRaffleKey,
@@ -14,10 +20,11 @@ impl Contract {
         );
     }
 
-    fn get_winner(&self) -> u32 {
+    fn get_winner(&mut self) -> u32 {
+        let random = self.next_random(&env::predecessor_account_id(), 0);
         let mut lazy_raffle = get_raffle_collection(StorageKey::AirdropLazyKey);
         let mut raffle = lazy_raffle.get().expect("Airdrop raffle doesn't exist");
-        let index = raffle.draw().expect("No more tokens left");
+        let index = raffle.draw(random).expect("No more tokens left");
         lazy_raffle.set(&raffle);
         index
     }
@@ -29,32 +36,36 @@ impl Contract {
             .get_winners(index, limit)
     }
 
-    // This was the big offender.  It is too expensive on mainnet to use this to find the owner of the token.
-    // fn nft_token_minted(&self, index: u32) -> (TokenId, AccountId) {
-    //     let from_index = Some(U128::from(index as u128));
-    //     let tokens = self.nft_tokens(from_index, Some(1));
-    //     require!(tokens.len() == 1, format!("{}", tokens.len()));
-    //     let token = &tokens[0];
-    //     (token.token_id.clone(), token.owner_id.clone())
-    //     // token
-    // }
-
-    // This was a temporary hack since `nft_tokens` as too costly.
-    //  pub fn get_owner(&self, token_index: u32) -> AccountId {
-    //    let node = self.tokens.owner_by_id.node(token_index as u64).unwrap();
-    //     self.tokens.owner_by_id.get(&node.key).unwrap()
-    //  }
-
-    pub fn draw_airdrop_winner(&mut self) -> u32 {
+    /// Draw a minted token and return its current owner, who wins the airdrop
+    /// @allow ["::owner"]
+    pub fn draw_airdrop_winner(&mut self) -> AccountId {
         self.assert_owner();
-        self.get_winner()
+        let index = self.get_winner();
+        let (_, owner_id) = self
+            .token_minted_at(index as u64)
+            .unwrap_or_else(|| env::panic_str("Winning token doesn't exist"));
+        owner_id
+    }
+
+    /// Token ids and owners of the winners drawn so far
+    pub fn get_winning_tokens(
+        &self,
+        index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<(TokenId, AccountId)> {
+        self.get_winners(index, limit)
+            .into_iter()
+            .filter_map(|winner| self.token_minted_at(winner as u64))
+            .collect()
     }
 
-    pub fn mint_airdrop_token(&mut self, owner_id: AccountId, token_id: u32) -> Token {
+    /// Mint a token from the remaining supply to a winner
+    /// @allow ["::owner"]
+    pub fn mint_airdrop_token(&mut self, owner_id: AccountId) -> Token {
         self.assert_owner();
-        let token_id = token_id.to_string();
-        let token = self.internal_mint(token_id.to_string(), owner_id.clone(), None);
-        NearEvent::log_nft_mint(owner_id.to_string(), vec![token_id], None);
+        require!(self.tokens_left() > 0, "No NFTs left to mint");
+        let token = self.draw_and_mint(owner_id.clone(), None, 0);
+        log_mint(&owner_id, &[token.clone()]);
         token
     }
 }
@@ -75,3 +86,108 @@ where
     let inner_raffle = RaffleCollection::new(raffle_prefix, length, max_winners);
     raffle.set(&inner_raffle);
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_contract;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use std::collections::HashSet;
+
+    fn setup() -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .build());
+        new_contract(Sale::default())
+    }
+
+    fn setup_minted() -> Contract {
+        let mut contract = setup();
+        (0..10).for_each(|i| {
+            contract.mint_airdrop_token(accounts(1 + i % 5));
+        });
+        contract
+    }
+
+    #[test]
+    fn draw_winners() {
+        let mut contract = setup_minted();
+        contract.initialize_airdrop(10, 3);
+        let owners: Vec<AccountId> = (0..3).map(|_| contract.draw_airdrop_winner()).collect();
+        let winners = contract.get_winning_tokens(None, None);
+        let token_ids: HashSet<&TokenId> = winners.iter().map(|(token_id, _)| token_id).collect();
+        assert_eq!(token_ids.len(), 3);
+        for ((token_id, owner_id), drawn) in winners.iter().zip(owners.iter()) {
+            assert_eq!(owner_id, drawn);
+            assert_eq!(
+                &contract.nft_token(token_id.clone()).unwrap().owner_id,
+                drawn
+            );
+        }
+        assert!(contract
+            .get_winners(None, None)
+            .iter()
+            .all(|winner| *winner < 10));
+    }
+
+    #[test]
+    fn mint_index_follows_mint_order() {
+        let contract = setup_minted();
+        assert_eq!(contract.nft_mint_index_len(), 10);
+        let token = contract.nft_token_by_mint_index(3).unwrap();
+        assert_eq!(token.owner_id, accounts(4));
+        assert!(contract.nft_token_by_mint_index(10).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't raffle more tokens than have been minted")]
+    fn only_minted_tokens_are_raffled() {
+        let mut contract = setup();
+        contract.mint_airdrop_token(accounts(1));
+        contract.initialize_airdrop(2, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "No more tokens left")]
+    fn no_more_than_max_winners() {
+        let mut contract = setup_minted();
+        contract.initialize_airdrop(10, 1);
+        contract.draw_airdrop_winner();
+        contract.draw_airdrop_winner();
+    }
+
+    #[test]
+    #[should_panic(expected = "Raffle is already initialized")]
+    fn initialize_once() {
+        let mut contract = setup_minted();
+        contract.initialize_airdrop(10, 1);
+        contract.initialize_airdrop(10, 1);
+    }
+
+    #[test]
+    fn mint_to_winner() {
+        let mut contract = setup();
+        let token = contract.mint_airdrop_token(accounts(1));
+        assert_eq!(token.owner_id, accounts(1));
+        assert_eq!(contract.tokens_left(), 9);
+        let logs = get_logs();
+        let mint_log = logs.last().unwrap();
+        assert!(mint_log.starts_with("EVENT_JSON:"));
+        assert!(mint_log.contains(r#""event":"nft_mint""#));
+        assert!(mint_log.contains(&token.token_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Method is private to owner")]
+    fn only_owner_draws() {
+        let mut contract = setup_minted();
+        contract.initialize_airdrop(10, 1);
+        testing_env!(VMContextBuilder::new()
+            .signer_account_id(accounts(1))
+            .build());
+        contract.draw_airdrop_winner();
+    }
+}
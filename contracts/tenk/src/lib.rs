@@ -1,11 +1,14 @@
-use linkdrop::LINKDROP_DEPOSIT;
+use linkdrop::{
+  default_linkdrop_factory, LinkdropCreation, LinkdropKey, LinkdropStatus, DEFAULT_LINKDROP_FEE,
+  LINKDROP_DEPOSIT,
+};
 use near_contract_standards::non_fungible_token::{
   metadata::{NFTContractMetadata, TokenMetadata, NFT_METADATA_SPEC},
   refund_deposit_to_account, NonFungibleToken, Token, TokenId,
 };
 use near_sdk::{
   borsh::{self, BorshDeserialize, BorshSerialize},
-  collections::{LazyOption, LookupMap, UnorderedSet},
+  collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector},
   env, ext_contract,
   json_types::{Base64VecU8, U128},
   log, near_bindgen, require,
@@ -21,20 +24,36 @@ use std::convert::{TryFrom, TryInto};
 #[witgen]
 type TimestampMs = u64;
 
+#[cfg(feature = "airdrop")]
+mod airdrop;
 pub mod linkdrop;
+mod lottery;
 mod owner;
 pub mod payout;
 mod raffle;
+mod raffle_collection;
+mod series;
 mod standards;
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod test_utils;
+mod traits;
 mod types;
+mod upgrade;
 mod util;
 mod views;
 
+use lottery::*;
 use payout::*;
 use raffle::Raffle;
+use series::*;
 use standards::*;
+use traits::*;
 use types::*;
-use util::{current_time_ms, is_promise_success, log_mint, refund};
+use util::{
+  current_time_ms, is_promise_success, log_contract_metadata_update, log_mint,
+  log_nft_metadata_update, refund, set_base_uri,
+};
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -45,6 +64,7 @@ pub struct Contract {
   raffle: Raffle,
   pending_tokens: u32,
   /// Linkdrop fields will be removed once proxy contract is deployed
+  /// Keys created before a linkdrop could hold more than one token
   pub accounts: LookupMap<PublicKey, bool>,
   /// Whitelist
   whitelist: LookupMap<AccountId, Allowance>,
@@ -55,6 +75,65 @@ pub struct Contract {
 
   /// extension for generating media links
   media_extension: Option<String>,
+
+  /// Once set, contract metadata can no longer be changed
+  metadata_frozen: bool,
+
+  /// Templates for the metadata of each minted token
+  metadata_template: MetadataTemplate,
+
+  /// Metadata loaded per token before it is minted
+  token_metadata: LookupMap<TokenId, TokenMetadataOverride>,
+  /// Number of tokens that have had metadata loaded
+  token_metadata_loaded: u32,
+
+  /// Weighted traits drawn for each token and stored in `extra`
+  traits: TraitTable,
+
+  /// Accounts besides the owner and admins allowed to update token metadata, e.g. game contracts
+  metadata_updaters: UnorderedSet<AccountId>,
+
+  /// How raffle indexes are turned into token ids
+  token_id_format: TokenIdFormat,
+
+  /// Additional drops minted from this contract, each with its own pool and sale
+  series: UnorderedMap<SeriesId, Series>,
+  /// Presale and public allowances per series
+  series_whitelist: LookupMap<(SeriesId, AccountId), Allowance>,
+  /// Tokens minted across all series
+  series_minted: u64,
+
+  /// Base URIs of token ranges uploaded separately from `base_uri`
+  base_uri_ranges: Vec<BaseUriRange>,
+
+  /// Counter mixed into the randomness of every draw
+  draw_nonce: u64,
+
+  /// Whether tokens are drawn randomly or handed out in order
+  mint_order: MintOrder,
+
+  /// Token ids in the order they were minted
+  minted_tokens: Vector<TokenId>,
+
+  /// Presale lottery entrants register for, if one was started
+  lottery: Option<Lottery>,
+
+  /// Every linkdrop created and what happened to it
+  linkdrops: LookupMap<PublicKey, LinkdropKey>,
+  /// Keys of linkdrops that haven't been claimed or revoked, so that expired ones can be found
+  pending_linkdrops: UnorderedSet<PublicKey>,
+  /// Keys of the linkdrops each account created
+  linkdrops_by_creator: LookupMap<AccountId, Vector<PublicKey>>,
+  /// Raffle indexes removed with `reduce_supply`, which can never be minted
+  removed_tokens: LookupSet<u64>,
+  /// Charged to anyone but the owner for each linkdrop token
+  linkdrop_fee: Balance,
+  /// Account that creates new accounts for linkdrop claims
+  linkdrop_factory: AccountId,
+  /// NEAR sent to the account claiming a linkdrop
+  linkdrop_claim_deposit: Balance,
+  /// Tokens minted before the upgrade that were added to `minted_tokens`
+  minted_tokens_backfilled: u64,
 }
 
 const GAS_REQUIRED_FOR_LINKDROP: Gas = Gas(parse_gas!("40 Tgas") as u64);
@@ -72,9 +151,22 @@ trait Linkdrop {
     gas_required: Gas,
   ) -> Promise;
 
-  fn on_send_with_callback(&mut self) -> Promise;
+  fn on_send_with_callback(&mut self, public_key: PublicKey, num: u16) -> Promise;
+
+  fn on_create_linkdrops(&mut self, results: Vec<LinkdropCreation>) -> Vec<LinkdropCreation>;
+
+  fn link_callback(&mut self, account_id: AccountId, mint_for_free: bool, num: u16) -> Vec<Token>;
 
-  fn link_callback(&mut self, account_id: AccountId, mint_for_free: bool) -> Token;
+  fn escrow_callback(&mut self, account_id: AccountId, token_id: TokenId) -> Token;
+
+  fn on_claim_key_deleted(
+    &mut self,
+    public_key: PublicKey,
+    account_id: AccountId,
+    new_public_key: PublicKey,
+  ) -> bool;
+
+  fn on_account_created(&mut self, public_key: PublicKey, account_id: AccountId) -> bool;
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -88,13 +180,48 @@ enum StorageKey {
   LinkdropKeys,
   Whitelist,
   Admins,
+  Version,
+  TokenMetadataOverrides,
+  MetadataUpdaters,
+  Series,
+  SeriesWhitelist,
+  SeriesRaffle {
+    series_id: SeriesId,
+  },
+  #[cfg_attr(not(feature = "airdrop"), allow(dead_code))]
+  AirdropLazyKey,
+  #[cfg_attr(not(feature = "airdrop"), allow(dead_code))]
+  AirdropRaffleKey,
+  MintedTokens,
+  LotteryEntrants {
+    round: u32,
+  },
+  LotteryResults {
+    round: u32,
+  },
+  LotteryDraw {
+    round: u32,
+  },
+  Linkdrops,
+  PendingLinkdrops,
+  LinkdropsByCreatorIndex,
+  LinkdropsByCreator {
+    creator: AccountId,
+  },
+  RemovedTokens,
 }
 
 #[near_bindgen]
 impl Contract {
   #[init]
-  pub fn new_default_meta(owner_id: AccountId, size: u32, media_extension: Option<String>) -> Self {
-    Self::new(
+  pub fn new_default_meta(
+    owner_id: AccountId,
+    size: u32,
+    media_extension: Option<String>,
+    metadata_template: Option<MetadataTemplate>,
+    token_id_format: Option<TokenIdFormat>,
+  ) -> Self {
+    let mut contract = Self::new(
             owner_id,
             NFTContractMetadata {
               name: String::from("NEARGotchi"),
@@ -129,7 +256,11 @@ impl Contract {
               presale_start: None,
             },
             media_extension,
-        )
+            metadata_template,
+            token_id_format,
+        );
+    contract.traits = TraitTable::animal_types();
+    contract
   }
 
   #[init]
@@ -139,15 +270,21 @@ impl Contract {
     size: u32,
     sale: Sale,
     media_extension: Option<String>,
+    metadata_template: Option<MetadataTemplate>,
+    token_id_format: Option<TokenIdFormat>,
   ) -> Self {
     metadata.assert_valid();
     sale.validate();
+    sale.validate_schedule();
+    let token_id_format = token_id_format.unwrap_or_default();
+    token_id_format.validate();
     if let Some(ext) = media_extension.as_ref() {
       require!(
         !ext.starts_with('.'),
         "media extension must not start with '.'"
       );
     }
+    upgrade::write_version();
     Self {
       tokens: NonFungibleToken::new(
         StorageKey::NonFungibleToken,
@@ -164,6 +301,29 @@ impl Contract {
       sale,
       admins: UnorderedSet::new(StorageKey::Admins),
       media_extension,
+      metadata_frozen: false,
+      metadata_template: metadata_template.unwrap_or_default(),
+      token_metadata: LookupMap::new(StorageKey::TokenMetadataOverrides),
+      token_metadata_loaded: 0,
+      traits: TraitTable::default(),
+      metadata_updaters: UnorderedSet::new(StorageKey::MetadataUpdaters),
+      token_id_format,
+      series: UnorderedMap::new(StorageKey::Series),
+      series_whitelist: LookupMap::new(StorageKey::SeriesWhitelist),
+      series_minted: 0,
+      base_uri_ranges: vec![],
+      draw_nonce: 0,
+      mint_order: MintOrder::default(),
+      minted_tokens: Vector::new(StorageKey::MintedTokens),
+      lottery: None,
+      linkdrops: LookupMap::new(StorageKey::Linkdrops),
+      pending_linkdrops: UnorderedSet::new(StorageKey::PendingLinkdrops),
+      linkdrops_by_creator: LookupMap::new(StorageKey::LinkdropsByCreatorIndex),
+      removed_tokens: LookupSet::new(StorageKey::RemovedTokens),
+      linkdrop_fee: DEFAULT_LINKDROP_FEE,
+      linkdrop_factory: default_linkdrop_factory(),
+      linkdrop_claim_deposit: LINKDROP_DEPOSIT,
+      minted_tokens_backfilled: 0,
     }
   }
 
@@ -194,6 +354,34 @@ impl Contract {
     tokens
   }
 
+  /// Update the metadata of minted tokens, setting `updated_at` and emitting `nft_metadata_update`.
+  /// Attached deposit must cover any extra storage used.
+  /// @allow ["::admins", "::owner", "::metadata_updaters"]
+  #[payable]
+  pub fn nft_update_metadata(
+    &mut self,
+    token_ids: Vec<TokenId>,
+    patch: TokenMetadataPatch,
+  ) -> bool {
+    self.assert_metadata_updater();
+    patch.validate();
+    let initial_storage_usage = env::storage_usage();
+    let updated_at = current_time_ms().to_string();
+    let token_metadata_by_id = self.tokens.token_metadata_by_id.as_mut().unwrap();
+    token_ids.iter().for_each(|token_id| {
+      let mut metadata = token_metadata_by_id
+        .get(token_id)
+        .unwrap_or_else(|| env::panic_str(&format!("Token {} doesn't exist", token_id)));
+      patch.apply(&mut metadata);
+      metadata.updated_at = Some(updated_at.clone());
+      token_metadata_by_id.insert(token_id, &metadata);
+    });
+    let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+    refund_deposit_to_account(storage_used, env::predecessor_account_id());
+    log_nft_metadata_update(&token_ids);
+    true
+  }
+
   fn nft_mint_many_ungaurded(
     &mut self,
     num: u16,
@@ -208,20 +396,12 @@ impl Contract {
 
     // Mint tokens
     let tokens: Vec<Token> = (0..num)
-      .map(|_| self.draw_and_mint(owner_id.clone(), None))
+      .map(|i| self.draw_and_mint(owner_id.clone(), None, i))
       .collect();
 
     if !mint_for_free {
       let storage_used = env::storage_usage() - initial_storage_usage;
-      if let Some(royalties) = &self.sale.initial_royalties {
-        // Keep enough funds to cover storage and split the rest as royalties
-        let storage_cost = env::storage_byte_cost() * storage_used as Balance;
-        let left_over_funds = env::attached_deposit() - storage_cost;
-        royalties.send_funds(left_over_funds, &self.tokens.owner_id);
-      } else {
-        // Keep enough funds to cover storage and send rest to contract owner
-        refund_deposit_to_account(storage_used, self.tokens.owner_id.clone());
-      }
+      self.pay_for_mint(storage_used, self.sale.initial_royalties.as_ref());
     }
     // Emit mint event log
     log_mint(owner_id, &tokens);
@@ -232,9 +412,13 @@ impl Contract {
 
   #[private]
   #[payable]
-  pub fn on_send_with_callback(&mut self) {
+  pub fn on_send_with_callback(&mut self, public_key: PublicKey, num: u16) {
     if !is_promise_success(None) {
-      self.pending_tokens -= 1;
+      if let Some(linkdrop) = self.close_linkdrop(&public_key, LinkdropStatus::Revoked, None) {
+        self.return_escrowed_token(&linkdrop);
+        self.restore_whitelist_allowance(&linkdrop.creator, num);
+      }
+      self.pending_tokens -= num as u32;
       let amount = env::attached_deposit();
       if amount > 0 {
         refund(&env::signer_account_id(), amount);
@@ -244,10 +428,18 @@ impl Contract {
 
   #[payable]
   #[private]
-  pub fn link_callback(&mut self, account_id: AccountId, mint_for_free: bool) -> Token {
+  pub fn link_callback(
+    &mut self,
+    account_id: AccountId,
+    mint_for_free: bool,
+    num: u16,
+  ) -> Vec<Token> {
     if is_promise_success(None) {
-      self.pending_tokens -= 1;
-      self.nft_mint_many_ungaurded(1, &account_id, mint_for_free)[0].clone()
+      self.assert_claiming(&env::signer_account_pk());
+      self.pending_tokens -= num as u32;
+      let tokens = self.nft_mint_many_ungaurded(num, &account_id, mint_for_free);
+      self.record_linkdrop_tokens(&env::signer_account_pk(), &tokens);
+      tokens
     } else {
       env::panic_str("Promise before Linkdrop callback failed");
     }
@@ -292,6 +484,27 @@ impl Contract {
     minter.as_str() == self.tokens.owner_id.as_str() || minter.as_str() == TECH_BACKUP_OWNER
   }
 
+  fn assert_metadata_not_frozen(&self) {
+    require!(!self.metadata_frozen, "Contract metadata is frozen");
+  }
+
+  fn assert_nothing_minted(&self) {
+    require!(
+      self.nft_total_supply().0 == 0 && self.pending_tokens == 0,
+      "Can only be changed before the sale"
+    );
+  }
+
+  fn assert_metadata_updater(&self) {
+    let account_id = env::predecessor_account_id();
+    require!(
+      self.is_owner(&account_id)
+        || self.is_admin(&account_id)
+        || self.metadata_updaters.contains(&account_id),
+      "Method is private to owner, admins and metadata updaters"
+    );
+  }
+
   fn assert_owner_or_admin(&self) {
     require!(
       self.signer_is_owner_or_admin(),
@@ -314,17 +527,38 @@ impl Contract {
   }
 
   fn full_link_price(&self, minter: &AccountId) -> u128 {
-    LINKDROP_DEPOSIT
+    self.linkdrop_claim_deposit
       + if self.is_owner(minter) {
-        parse_near!("0 mN")
+        0
       } else {
-        parse_near!("8 mN")
+        self.linkdrop_fee
       }
   }
 
-  fn draw_and_mint(&mut self, token_owner_id: AccountId, refund: Option<AccountId>) -> Token {
-    let id = self.raffle.draw();
-    self.internal_mint(id.to_string(), token_owner_id, refund)
+  /// Randomness for the `index`th draw of the current call
+  fn next_random(&mut self, minter: &AccountId, index: u16) -> u64 {
+    self.draw_nonce += 1;
+    util::random_u64(self.draw_nonce, minter, index as u64)
+  }
+
+  fn draw_and_mint(
+    &mut self,
+    token_owner_id: AccountId,
+    refund: Option<AccountId>,
+    index: u16,
+  ) -> Token {
+    let id = match self.mint_order {
+      MintOrder::Random => {
+        let random = self.next_random(&token_owner_id, index);
+        self.raffle.draw(random)
+      }
+      MintOrder::Sequential => self
+        .raffle
+        .shift()
+        .unwrap_or_else(|| env::panic_str("No NFTs left to mint")),
+    };
+    let token_id = self.token_id_format.format(id);
+    self.internal_mint(token_id, token_owner_id, refund)
   }
 
   fn internal_mint(
@@ -333,40 +567,60 @@ impl Contract {
     token_owner_id: AccountId,
     refund_id: Option<AccountId>,
   ) -> Token {
-    let token_metadata = Some(self.create_metadata(&token_id));
+    let token_metadata = self.create_metadata(&token_id);
+    self.internal_mint_with_metadata(token_id, token_metadata, token_owner_id, refund_id)
+  }
+
+  fn internal_mint_with_metadata(
+    &mut self,
+    token_id: String,
+    mut token_metadata: TokenMetadata,
+    token_owner_id: AccountId,
+    refund_id: Option<AccountId>,
+  ) -> Token {
+    if let Some(loaded) = self.token_metadata.remove(&token_id) {
+      loaded.apply(&mut token_metadata);
+    }
+    let token_metadata = Some(token_metadata);
+    self.minted_tokens.push(&token_id);
     self
       .tokens
       .internal_mint_with_refund(token_id, token_owner_id, token_metadata, refund_id)
   }
 
+  /// Token id and current owner of the `index`th token minted
+  pub(crate) fn token_minted_at(&self, index: u64) -> Option<(TokenId, AccountId)> {
+    let token_id = self.minted_tokens.get(index)?;
+    let owner_id = self.tokens.owner_by_id.get(&token_id)?;
+    Some((token_id, owner_id))
+  }
+
   fn create_metadata(&mut self, token_id: &str) -> TokenMetadata {
-    let media = Some(format!(
-      "{}.{}",
-      token_id,
-      self.media_extension.as_ref().unwrap_or(&"png".to_string())
-    ));
-    let reference = Some(format!("{}.json", token_id));
-    let title = Some(format!(
-      "{} #{}",
-      self.metadata.get().unwrap().name,
-      token_id.to_string()
-    ));
-    let animal_type = (crate::util::get_random_number(env::block_timestamp() as u32) % 3) + 1;
-    let extra = Some(animal_type.to_string());
-    TokenMetadata {
-      title, // ex. "Arch Nemesis: Mail Carrier" or "Parcel #5055"
-      media, // URL to associated media, preferably to decentralized, content-addressed storage
-      issued_at: Some(current_time_ms().to_string()), // ISO 8601 datetime when token was issued or minted
-      reference,            // URL to an off-chain JSON file with more info.
-      description: None,    // free-form description
-      media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
-      copies: None, // number of copies of this set of metadata in existence when token was minted.
-      expires_at: None, // ISO 8601 datetime when token expires
-      starts_at: None, // ISO 8601 datetime when token starts being valid
-      updated_at: None, // ISO 8601 datetime when token was last updated
-      extra,        // anything extra the NFT wants to store on-chain. Can be stringified JSON.
-      reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+    let name = self.metadata.get().unwrap().name;
+    let ext = self.media_extension.as_deref().unwrap_or("png");
+    let mut metadata = self.metadata_template.render(token_id, &name, ext);
+    if let Some(base_uri) = self.base_uri_of(token_id) {
+      set_base_uri(&mut metadata, &base_uri);
+    }
+    // Traits are only drawn, and counted, for tokens that won't get a loaded `extra`
+    let loaded_extra = self
+      .token_metadata
+      .get(&token_id.to_string())
+      .map_or(false, |loaded| loaded.extra.is_some());
+    if metadata.extra.is_none() && !loaded_extra {
+      metadata.extra = self.traits.draw(token_id);
     }
+    metadata
+  }
+
+  /// Base URI of the range `token_id` belongs to, if it has its own
+  fn base_uri_of(&self, token_id: &str) -> Option<String> {
+    let index = self.token_id_format.parse(token_id)?;
+    self
+      .base_uri_ranges
+      .iter()
+      .find(|range| range.contains(index))
+      .map(|range| range.base_uri.clone())
   }
 
   fn use_whitelist_allowance(&mut self, account_id: &AccountId, num: u16) {
@@ -377,6 +631,17 @@ impl Contract {
     }
   }
 
+  /// Give back allowance used for tokens that weren't minted
+  fn restore_whitelist_allowance(&mut self, account_id: &AccountId, num: u16) {
+    if self.is_owner(account_id) {
+      return;
+    }
+    if let Some(mut allowance) = self.whitelist.get(account_id) {
+      allowance.return_num(num);
+      self.whitelist.insert(account_id, &allowance);
+    }
+  }
+
   fn get_whitelist_allowance(&self, account_id: &AccountId) -> Allowance {
     self
       .whitelist
@@ -406,22 +671,104 @@ impl Contract {
   }
 
   fn get_status(&self) -> Status {
-    if self.tokens_left() == 0 {
-      return Status::SoldOut;
-    }
-    let current_time = current_time_ms();
-    match (self.sale.presale_start, self.sale.public_sale_start) {
-      (_, Some(public)) if public < current_time => Status::Open,
-      (Some(pre), _) if pre < current_time => Status::Presale,
-      (_, _) => Status::Closed,
-    }
+    self.sale.status(self.tokens_left())
   }
 
   fn price(&self) -> u128 {
-    match self.get_status() {
-      Status::Presale | Status::Closed => self.sale.presale_price.unwrap_or(self.sale.price),
-      Status::Open | Status::SoldOut => self.sale.price,
+    self.sale.current_price(&self.get_status())
+  }
+
+  fn pay_for_mint(&self, storage_used: u64, initial_royalties: Option<&Royalties>) {
+    if let Some(royalties) = initial_royalties {
+      // Keep enough funds to cover storage and split the rest as royalties
+      let storage_cost = env::storage_byte_cost() * storage_used as Balance;
+      let left_over_funds = env::attached_deposit() - storage_cost;
+      royalties.send_funds(left_over_funds, &self.tokens.owner_id);
+    } else {
+      // Keep enough funds to cover storage and send rest to contract owner
+      refund_deposit_to_account(storage_used, self.tokens.owner_id.clone());
     }
-    .into()
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::new_contract;
+  use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+  use near_sdk::testing_env;
+
+  fn context(account_id: AccountId) -> VMContextBuilder {
+    let mut context = VMContextBuilder::new();
+    context
+      .signer_account_id(account_id.clone())
+      .predecessor_account_id(account_id)
+      .prepaid_gas(Gas(parse_gas!("300 Tgas") as u64));
+    context
+  }
+
+  /// Mint a token to `accounts(1)` and return its id
+  fn setup_minted_token() -> (Contract, TokenId) {
+    testing_env!(context(accounts(0)).build());
+    let mut contract = new_contract(Sale::default());
+    let token_id = contract.draw_and_mint(accounts(1), None, 0).token_id;
+    (contract, token_id)
+  }
+
+  fn update_metadata(
+    contract: &mut Contract,
+    account_id: AccountId,
+    token_id: &TokenId,
+    json: &str,
+  ) -> TokenMetadata {
+    testing_env!(context(account_id)
+      .attached_deposit(parse_near!("1 N"))
+      .block_timestamp(5_000_000)
+      .build());
+    let patch: TokenMetadataPatch = near_sdk::serde_json::from_str(json).unwrap();
+    contract.nft_update_metadata(vec![token_id.clone()], patch);
+    contract
+      .nft_token(token_id.clone())
+      .unwrap()
+      .metadata
+      .unwrap()
+  }
+
+  #[test]
+  fn metadata_updater_updates_tokens() {
+    let (mut contract, token_id) = setup_minted_token();
+    let media = contract
+      .nft_token(token_id.clone())
+      .unwrap()
+      .metadata
+      .unwrap()
+      .media;
+    contract.add_metadata_updater(accounts(3));
+    let metadata = update_metadata(&mut contract, accounts(3), &token_id, r#"{"title": "new"}"#);
+    assert_eq!(metadata.title.as_deref(), Some("new"));
+    assert_eq!(metadata.media, media);
+    assert_eq!(metadata.updated_at.as_deref(), Some("5"));
+
+    let logs = get_logs();
+    let update_log = logs.last().unwrap();
+    assert!(update_log.starts_with("EVENT_JSON:"));
+    assert!(update_log.contains(r#""event":"nft_metadata_update""#));
+    assert!(update_log.contains(&format!(r#""token_ids":["{}"]"#, token_id)));
+  }
+
+  #[test]
+  fn null_clears_token_metadata() {
+    let (mut contract, token_id) = setup_minted_token();
+    update_metadata(&mut contract, accounts(0), &token_id, r#"{"title": "new"}"#);
+    let metadata = update_metadata(&mut contract, accounts(0), &token_id, r#"{"title": null}"#);
+    assert_eq!(metadata.title, None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Method is private to owner, admins and metadata updaters")]
+  fn only_metadata_updaters_update_tokens() {
+    let (mut contract, token_id) = setup_minted_token();
+    update_metadata(&mut contract, accounts(3), &token_id, r#"{"title": "new"}"#);
   }
 }
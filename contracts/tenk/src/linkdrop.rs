@@ -1,14 +1,19 @@
 use crate::*;
 use near_sdk::{
-    env, ext_contract, json_types::U128, log, near_bindgen, AccountId, Balance, Gas, Promise,
-    PublicKey,
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    env, ext_contract,
+    json_types::U128,
+    log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseOrValue, PromiseResult, PublicKey,
 };
 use near_units::parse_near;
 
 /// 0.064311394105062020653824 N
 pub(crate) const ACCESS_KEY_ALLOWANCE: u128 = parse_near!("0 N");
 
+/// NEAR sent to the account claiming a linkdrop, until changed with `update_linkdrop_claim_deposit`
 pub(crate) const LINKDROP_DEPOSIT: u128 = parse_near!("0.02 N");
+/// Fee charged to anyone but the owner for creating a linkdrop, until changed with `update_linkdrop_fee`
+pub(crate) const DEFAULT_LINKDROP_FEE: u128 = parse_near!("8 mN");
 /// can take 0.5 of access key since gas required is 6.6 times what was actually used
 const ON_CREATE_ACCOUNT_GAS: Gas = Gas(30_000_000_000_000);
 const NO_DEPOSIT: Balance = 0;
@@ -16,10 +21,198 @@ const NO_DEPOSIT: Balance = 0;
 /// Gas attached to the callback from account creation.
 pub const ON_CREATE_ACCOUNT_CALLBACK_GAS: Gas = Gas(10_000_000_000_000);
 
+/// Most tokens one linkdrop key can claim, so that minting them fits in a claim's gas
+pub(crate) const MAX_LINKDROP_TOKENS: u16 = 10;
+/// Most keys `create_linkdrops` adds in one call, so that adding them fits in its gas
+pub(crate) const MAX_LINKDROP_BATCH: u16 = 50;
+/// Gas added to `link_callback` for each token after the first
+const GAS_PER_LINKDROP_TOKEN: Gas = Gas(8_000_000_000_000);
+/// How long a claim can stay `Claiming` before `resolve_stale_linkdrop` can settle it
+pub(crate) const STALE_CLAIM_MS: TimestampMs = 10 * 60 * 1000;
+
+/// What a linkdrop key claims and who paid for it
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LinkdropKey {
+    /// Number of tokens minted to the claimer
+    pub num: u16,
+    pub mint_for_free: bool,
+    /// Refunded if the linkdrop is revoked
+    pub creator: AccountId,
+    pub created_at: TimestampMs,
+    /// After this the key can't claim and anyone can reclaim it
+    pub expires_at: Option<TimestampMs>,
+    /// Amount the creator paid
+    pub cost: U128,
+    /// Part of `cost` kept as the linkdrop fee
+    pub fee: U128,
+    /// NEAR sent to the claiming account, or refunded to the creator if it isn't needed
+    pub deposit: U128,
+    pub status: LinkdropStatus,
+    /// Account the tokens were claimed to
+    pub claimer: Option<AccountId>,
+    /// Tokens minted or transferred once claimed
+    pub token_ids: Vec<TokenId>,
+    /// Token held by the contract that is transferred on claim instead of minting
+    pub escrowed_token: Option<TokenId>,
+    /// When the current claim started
+    pub claim_started_at: Option<TimestampMs>,
+    /// Account created by an earlier claim that failed to mint. Later claims go to it
+    /// and the deposit, already sent with the account, isn't paid again.
+    pub created_account: Option<AccountId>,
+    /// Created when each key held one token, so only its deposit is known to have been paid
+    pub legacy: bool,
+}
+
+/// Whether a linkdrop can still be claimed
+#[witgen]
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum LinkdropStatus {
+    Pending,
+    /// Key deleted and the claim's promises in flight. Becomes `Claimed` once they succeed,
+    /// or `Pending` again with the key added back if any of them fail.
+    Claiming,
+    Claimed,
+    Revoked,
+    /// Key couldn't be added, so it can be used for another linkdrop
+    Failed,
+}
+
+/// A linkdrop and what happened to it
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LinkdropInfo {
+    pub public_key: PublicKey,
+    pub creator: AccountId,
+    pub created_at: TimestampMs,
+    pub expires_at: Option<TimestampMs>,
+    pub mint_for_free: bool,
+    /// Number of tokens the linkdrop claims
+    pub num: u16,
+    pub status: LinkdropStatus,
+    pub claimer: Option<AccountId>,
+    pub token_ids: Vec<TokenId>,
+    pub escrowed_token: Option<TokenId>,
+}
+
+impl LinkdropKey {
+    fn new(
+        num: u16,
+        mint_for_free: bool,
+        creator: AccountId,
+        expires_at: Option<TimestampMs>,
+        cost: Balance,
+        fee: Balance,
+        deposit: Balance,
+    ) -> Self {
+        Self {
+            num,
+            mint_for_free,
+            creator,
+            created_at: current_time_ms(),
+            expires_at,
+            cost: cost.into(),
+            fee: fee.into(),
+            deposit: deposit.into(),
+            status: LinkdropStatus::Pending,
+            claimer: None,
+            token_ids: vec![],
+            escrowed_token: None,
+            claim_started_at: None,
+            created_account: None,
+            legacy: false,
+        }
+    }
+
+    /// Key created when each key held one token and nothing else was recorded.
+    /// Claiming it pays `mint_payment` for the token, but revoking it only refunds the deposit.
+    fn legacy(mint_for_free: bool, owner_id: AccountId, mint_payment: Balance) -> Self {
+        Self {
+            created_at: 0,
+            legacy: true,
+            ..Self::new(
+                1,
+                mint_for_free,
+                owner_id,
+                None,
+                LINKDROP_DEPOSIT + mint_payment,
+                0,
+                LINKDROP_DEPOSIT,
+            )
+        }
+    }
+
+    fn info(self, public_key: PublicKey) -> LinkdropInfo {
+        LinkdropInfo {
+            public_key,
+            creator: self.creator,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            mint_for_free: self.mint_for_free,
+            num: self.num,
+            status: self.status,
+            claimer: self.claimer,
+            token_ids: self.token_ids,
+            escrowed_token: self.escrowed_token,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| current_time_ms() >= expires_at)
+    }
+
+    /// Part of `cost` that pays for minting the tokens, forwarded when they are claimed
+    pub fn mint_payment(&self) -> Balance {
+        self.cost.0.saturating_sub(self.fee.0 + self.deposit.0)
+    }
+
+    /// What the creator gets back if the linkdrop is revoked
+    fn refund_amount(&self) -> Balance {
+        let paid = if self.legacy {
+            self.deposit.0
+        } else {
+            self.cost.0
+        };
+        if self.created_account.is_some() {
+            paid.saturating_sub(self.deposit.0)
+        } else {
+            paid
+        }
+    }
+}
+
+/// Whether a key passed to `create_linkdrops` was added
+#[witgen]
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LinkdropCreation {
+    pub public_key: PublicKey,
+    /// Why the key was skipped or couldn't be added, if it was
+    pub error: Option<String>,
+}
+
 #[ext_contract(ext_linkdrop)]
 trait ExtLinkdrop {
     fn create_account(&mut self, new_account_id: AccountId, new_public_key: PublicKey) -> Promise;
-    fn on_create_and_claim(&mut self, mint_for_free: bool) -> bool;
+    fn on_claim(&mut self, public_key: PublicKey, deposit_to: Option<AccountId>) -> bool;
+}
+
+/// Account that creates new accounts for `create_account_and_claim`, until changed with
+/// `update_linkdrop_factory`
+pub(crate) fn default_linkdrop_factory() -> AccountId {
+    AccountId::new_unchecked(
+        (if cfg!(feature = "testnet") {
+            "testnet"
+        } else {
+            "near"
+        })
+        .to_string(),
+    )
 }
 
 #[near_bindgen]
@@ -28,21 +221,32 @@ impl Contract {
     /// Takes ACCESS_KEY_ALLOWANCE as fee from deposit to cover account creation via an access key.
 
     /// Claim tokens for specific account that are attached to the public key this tx is signed with.
+    /// The linkdrop's deposit is sent to the account once the tokens are handed out.
     #[private]
     pub fn claim(&mut self, account_id: AccountId) -> Promise {
         // require!(false, "Cannot claim at this time try again later");
-        let (mint_for_free, deletion_promise) = self.delete_current_access_key();
+        let (key, linkdrop, deletion_promise) = self.delete_current_access_key(&account_id);
         deletion_promise
-            .then(Promise::new(account_id.clone()).transfer(LINKDROP_DEPOSIT))
-            .then(ext_self::link_callback(
-                account_id.clone(),
-                mint_for_free,
+            .then(self.claim_tokens(&account_id, &linkdrop))
+            .then(ext_linkdrop::on_claim(
+                key,
+                Some(account_id),
                 env::current_account_id(),
-                self.total_cost(1, &account_id).0,
-                GAS_REQUIRED_FOR_LINKDROP,
+                NO_DEPOSIT,
+                ON_CREATE_ACCOUNT_CALLBACK_GAS,
             ))
-            .then(ext_linkdrop::on_create_and_claim(
-                mint_for_free,
+    }
+
+    /// Claim tokens to an existing account without sending it NEAR.
+    /// The linkdrop's deposit is refunded to its creator once the tokens are minted.
+    #[private]
+    pub fn claim_existing(&mut self, account_id: AccountId) -> Promise {
+        let (key, linkdrop, deletion_promise) = self.delete_current_access_key(&account_id);
+        deletion_promise
+            .then(self.claim_tokens(&account_id, &linkdrop))
+            .then(ext_linkdrop::on_claim(
+                key,
+                Some(linkdrop.creator),
                 env::current_account_id(),
                 NO_DEPOSIT,
                 ON_CREATE_ACCOUNT_CALLBACK_GAS,
@@ -50,6 +254,7 @@ impl Contract {
     }
 
     /// Create new account and and claim tokens to it.
+    /// If an earlier claim already created the account, the tokens are claimed to it instead.
     #[private]
     pub fn create_account_and_claim(
         &mut self,
@@ -57,89 +262,1431 @@ impl Contract {
         new_public_key: PublicKey,
     ) -> Promise {
         // require!(false, "Cannot claim at this time try again later");
-        let (mint_for_free, deletion_promise) = self.delete_current_access_key();
-        deletion_promise
-            .and(self.create_account(new_account_id.clone(), new_public_key))
-            .then(ext_self::link_callback(
-                new_account_id.clone(),
-                mint_for_free,
+        let (key, linkdrop, deletion_promise) = self.delete_current_access_key(&new_account_id);
+        if linkdrop.created_account.is_some() {
+            return deletion_promise
+                .then(self.claim_tokens(&new_account_id, &linkdrop))
+                .then(ext_linkdrop::on_claim(
+                    key,
+                    None,
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    ON_CREATE_ACCOUNT_CALLBACK_GAS,
+                ));
+        }
+        let gas = Gas(ON_CREATE_ACCOUNT_GAS.0
+            + account_created_gas(&linkdrop).0
+            + ON_CREATE_ACCOUNT_CALLBACK_GAS.0);
+        deletion_promise.then(ext_self::on_claim_key_deleted(
+            key,
+            new_account_id,
+            new_public_key,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            gas,
+        ))
+    }
+
+    /// Create the account once the linkdrop's key is deleted. If the key couldn't be deleted
+    /// no account is created and the linkdrop is pending again.
+    #[private]
+    pub fn on_claim_key_deleted(
+        &mut self,
+        public_key: PublicKey,
+        account_id: AccountId,
+        new_public_key: PublicKey,
+    ) -> PromiseOrValue<bool> {
+        let linkdrop = match self.linkdrops.get(&public_key) {
+            Some(linkdrop) if linkdrop.status == LinkdropStatus::Claiming => linkdrop,
+            _ => return PromiseOrValue::Value(false),
+        };
+        if !is_promise_success(None) {
+            self.mark_pending(&public_key, linkdrop);
+            return PromiseOrValue::Value(false);
+        }
+        self.create_account(account_id.clone(), new_public_key, linkdrop.deposit.0)
+            .then(ext_self::on_account_created(
+                public_key,
+                account_id,
                 env::current_account_id(),
-                self.total_cost(1, &new_account_id).0,
-                GAS_REQUIRED_FOR_LINKDROP,
+                NO_DEPOSIT,
+                account_created_gas(&linkdrop),
             ))
-            .then(ext_linkdrop::on_create_and_claim(
-                mint_for_free,
+            .into()
+    }
+
+    /// Claim the tokens once the factory created the account. The factory refunds the deposit
+    /// and returns `false` if it couldn't, in which case the key is added back.
+    #[private]
+    pub fn on_account_created(
+        &mut self,
+        public_key: PublicKey,
+        account_id: AccountId,
+    ) -> PromiseOrValue<bool> {
+        let mut linkdrop = match self.linkdrops.get(&public_key) {
+            Some(linkdrop) if linkdrop.status == LinkdropStatus::Claiming => linkdrop,
+            _ => return PromiseOrValue::Value(false),
+        };
+        if !account_created() {
+            self.reopen_linkdrop(public_key, linkdrop);
+            return PromiseOrValue::Value(false);
+        }
+        linkdrop.created_account = Some(account_id.clone());
+        self.linkdrops.insert(&public_key, &linkdrop);
+        self.claim_tokens(&account_id, &linkdrop)
+            .then(ext_linkdrop::on_claim(
+                public_key,
+                None,
                 env::current_account_id(),
                 NO_DEPOSIT,
                 ON_CREATE_ACCOUNT_CALLBACK_GAS,
             ))
+            .into()
     }
 
     /// Returns the balance associated with given key.
-    #[allow(unused_variables)]
     pub fn get_key_balance(&self) -> U128 {
-        LINKDROP_DEPOSIT.into()
+        self.get_linkdrop(&env::signer_account_pk())
+            .map_or(self.linkdrop_claim_deposit, |linkdrop| linkdrop.deposit.0)
+            .into()
     }
 
     pub fn check_key(&self, public_key: PublicKey) -> bool {
-        self.accounts.contains_key(&public_key)
+        self.get_linkdrop(&public_key).is_some()
+    }
+
+    /// A linkdrop, whether or not it has been claimed
+    pub fn linkdrop_info(&self, public_key: PublicKey) -> Option<LinkdropInfo> {
+        self.linkdrops
+            .get(&public_key)
+            .or_else(|| self.get_linkdrop(&public_key))
+            .map(|linkdrop| linkdrop.info(public_key))
+    }
+
+    /// Paginated list of linkdrops an account created, oldest first
+    pub fn linkdrops_by_creator(
+        &self,
+        creator: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<LinkdropInfo> {
+        self.linkdrops_by_creator
+            .get(&creator)
+            .map_or_else(Vec::new, |keys| {
+                keys.iter()
+                    .skip(from_index.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(u64::MAX) as usize)
+                    .filter_map(|key| self.linkdrop_info(key))
+                    .collect()
+            })
     }
 
+    /// Number of linkdrops an account created
+    pub fn linkdrop_count_by_creator(&self, creator: AccountId) -> u64 {
+        self.linkdrops_by_creator
+            .get(&creator)
+            .map_or(0, |keys| keys.len())
+    }
+
+    /// Number of tokens the key can claim, if it is a linkdrop
+    pub fn linkdrop_tokens(&self, public_key: PublicKey) -> Option<u16> {
+        self.get_linkdrop(&public_key).map(|linkdrop| linkdrop.num)
+    }
+
+    /// Create a one token linkdrop for each key, adding up to `MAX_LINKDROP_BATCH` keys in one batch.
+    /// Keys that are repeated or already linkdrops are skipped and reported in the result.
+    /// Each key is added on its own, and any that fail are released, refunded and reported,
+    /// so that they can be passed again.
+    /// @allow ["::admins", "::owner"]
+    #[payable]
+    pub fn create_linkdrops(
+        &mut self,
+        public_keys: Vec<PublicKey>,
+        expires_at: Option<TimestampMs>,
+    ) -> PromiseOrValue<Vec<LinkdropCreation>> {
+        self.assert_owner_or_admin();
+        require!(
+            public_keys.len() <= MAX_LINKDROP_BATCH as usize,
+            format!(
+                "Can create at most {} linkdrops at once",
+                MAX_LINKDROP_BATCH
+            )
+        );
+        assert_expiry(expires_at);
+        let results = self.check_new_linkdrop_keys(public_keys);
+        let keys: Vec<PublicKey> = results
+            .iter()
+            .filter(|result| result.error.is_none())
+            .map(|result| result.public_key.clone())
+            .collect();
+        if keys.is_empty() {
+            refund(&env::predecessor_account_id(), env::attached_deposit());
+            return PromiseOrValue::Value(results);
+        }
+
+        let account = &env::predecessor_account_id();
+        let (total_cost, fee) = self.charge_for_linkdrops(account, keys.len() as u16);
+        let linkdrop = LinkdropKey::new(
+            1,
+            self.is_owner(account),
+            account.clone(),
+            expires_at,
+            total_cost / keys.len() as Balance,
+            fee / keys.len() as Balance,
+            self.linkdrop_claim_deposit,
+        );
+        keys.into_iter()
+            .map(|key| self.add_key(key, linkdrop.clone()))
+            .reduce(Promise::and)
+            .unwrap()
+            .then(ext_self::on_create_linkdrops(
+                results,
+                env::current_account_id(),
+                total_cost,
+                GAS_REQUIRED_TO_CREATE_LINKDROP,
+            ))
+            .into()
+    }
+
+    /// Release the keys of a batch that couldn't be added, refunding their creator,
+    /// and report what happened to each key
     #[private]
-    pub fn on_create_and_claim(&mut self, mint_for_free: bool) {
+    #[payable]
+    pub fn on_create_linkdrops(&mut self, results: Vec<LinkdropCreation>) -> Vec<LinkdropCreation> {
+        let mut index = 0;
+        let mut refunded: Option<(AccountId, Balance)> = None;
+        let results = results
+            .into_iter()
+            .map(|mut result| {
+                if result.error.is_some() {
+                    return result;
+                }
+                let added = matches!(env::promise_result(index), PromiseResult::Successful(_));
+                index += 1;
+                if !added {
+                    if let Some(linkdrop) =
+                        self.close_linkdrop(&result.public_key, LinkdropStatus::Failed, None)
+                    {
+                        self.pending_tokens -= linkdrop.num as u32;
+                        self.restore_whitelist_allowance(&linkdrop.creator, linkdrop.num);
+                        let amount = refunded.as_ref().map_or(0, |(_, amount)| *amount);
+                        refunded =
+                            Some((linkdrop.creator.clone(), amount + linkdrop.refund_amount()));
+                    }
+                    result.error = Some("Failed to add key".to_string());
+                }
+                result
+            })
+            .collect();
+        if let Some((creator, amount)) = refunded {
+            refund(&creator, amount);
+        }
+        results
+    }
+
+    /// Buy `num` tokens, one by default, as a linkdrop that can be claimed with the key's private key.
+    /// Follows the same sale rules as minting and costs `cost_of_linkdrop` for each token.
+    #[payable]
+    pub fn purchase_linkdrop(
+        &mut self,
+        public_key: PublicKey,
+        num: Option<u16>,
+        expires_at: Option<TimestampMs>,
+    ) -> Promise {
+        let num = num.unwrap_or(1);
+        if let Some(limit) = self.sale.mint_rate_limit {
+            require!(num <= limit, "over mint limit");
+        }
+        self.reserve_linkdrop(public_key, num, expires_at)
+    }
+
+    /// Put a token you own behind `public_key`. Claiming the linkdrop transfers the token
+    /// instead of minting one, and revoking it returns the token to you.
+    /// Attached deposit must cover `cost_of_token_linkdrop`.
+    #[payable]
+    pub fn create_token_linkdrop(
+        &mut self,
+        public_key: PublicKey,
+        token_id: TokenId,
+        expires_at: Option<TimestampMs>,
+    ) -> Promise {
+        assert_expiry(expires_at);
+        let account = env::predecessor_account_id();
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(
+            owner_id == account,
+            "Only the token's owner can create a linkdrop for it"
+        );
+        let cost = self.cost_of_token_linkdrop(&account).0;
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= cost,
+            "Not enough attached deposit to create linkdrop"
+        );
+        refund(&account, deposit - cost);
+        self.tokens
+            .internal_transfer(&account, &env::current_account_id(), &token_id, None, None);
+        let linkdrop = LinkdropKey {
+            escrowed_token: Some(token_id),
+            ..LinkdropKey::new(
+                0,
+                false,
+                account,
+                expires_at,
+                cost,
+                cost - self.linkdrop_claim_deposit,
+                self.linkdrop_claim_deposit,
+            )
+        };
+        self.send(public_key.clone(), linkdrop)
+            .then(ext_self::on_send_with_callback(
+                public_key,
+                0,
+                env::current_account_id(),
+                cost,
+                GAS_REQUIRED_TO_CREATE_LINKDROP,
+            ))
+    }
+
+    /// Transfer the escrowed token of a linkdrop being claimed
+    #[private]
+    pub fn escrow_callback(&mut self, account_id: AccountId, token_id: TokenId) -> Token {
         if !is_promise_success(None) {
-            self.send(env::signer_account_pk(), mint_for_free);
+            env::panic_str("Promise before Linkdrop callback failed");
         }
+        self.assert_claiming(&env::signer_account_pk());
+        self.tokens.internal_transfer(
+            &env::current_account_id(),
+            &account_id,
+            &token_id,
+            None,
+            None,
+        );
+        let token = self.nft_token(token_id).unwrap();
+        self.record_linkdrop_tokens(&env::signer_account_pk(), &[token.clone()]);
+        token
     }
 
-    pub fn get_linkdrop_contract(&self) -> AccountId {
-        AccountId::new_unchecked(
-            (if cfg!(feature = "testnet") {
-                "testnet"
-            } else {
-                "near"
+    /// Delete the key of an unclaimed linkdrop, release or return its tokens and refund its creator
+    /// @allow ["::admins", "::owner"]
+    pub fn revoke_linkdrop(&mut self, public_key: PublicKey) -> Promise {
+        let linkdrop = self
+            .get_linkdrop(&public_key)
+            .unwrap_or_else(|| env::panic_str("No linkdrop for key"));
+        let account_id = env::predecessor_account_id();
+        require!(
+            account_id == linkdrop.creator
+                || self.is_owner(&account_id)
+                || self.is_admin(&account_id),
+            "Only the creator, owner or admins can revoke a linkdrop"
+        );
+        self.release_linkdrop(&public_key)
+    }
+
+    /// Revoke the expired linkdrops among `limit` pending ones starting at `from_index`,
+    /// refunding their creators. Revoking moves the last pending linkdrops into the freed
+    /// positions, so a later pass can find more. Returns the number revoked. Anyone can call this.
+    pub fn reclaim_expired_linkdrops(&mut self, from_index: Option<u64>, limit: u32) -> u32 {
+        let expired: Vec<PublicKey> = self
+            .pending_linkdrops
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit as usize)
+            .filter(|key| {
+                self.linkdrops
+                    .get(key)
+                    .map_or(false, |linkdrop| linkdrop.is_expired())
             })
-            .to_string(),
-        )
+            .collect();
+        expired.iter().for_each(|key| {
+            self.release_linkdrop(key);
+        });
+        expired.len() as u32
     }
+
+    /// Last step of a claim. If the tokens were handed out the linkdrop is `Claimed` and its
+    /// deposit sent to `deposit_to`, unless it already went to a created account. Otherwise its
+    /// key is added back so it can be claimed again.
+    /// Does nothing unless the linkdrop is `Claiming`. Returns whether the claim completed.
+    #[private]
+    pub fn on_claim(&mut self, public_key: PublicKey, deposit_to: Option<AccountId>) -> bool {
+        let linkdrop = match self.linkdrops.get(&public_key) {
+            Some(linkdrop) if linkdrop.status == LinkdropStatus::Claiming => linkdrop,
+            _ => return false,
+        };
+        if !is_promise_success(None) {
+            self.reopen_linkdrop(public_key, linkdrop);
+            return false;
+        }
+        self.complete_claim(&public_key, linkdrop, deposit_to);
+        true
+    }
+
+    /// Account that creates new accounts for `create_account_and_claim`
+    pub fn get_linkdrop_contract(&self) -> AccountId {
+        self.linkdrop_factory.clone()
+    }
+}
+
+/// Add a function call access key that can only claim linkdrops
+fn add_linkdrop_key(promise: Promise, key: PublicKey) -> Promise {
+    promise.add_access_key(
+        key,
+        ACCESS_KEY_ALLOWANCE,
+        env::current_account_id(),
+        "claim,create_account_and_claim".to_string(),
+    )
+}
+
+fn assert_expiry(expires_at: Option<TimestampMs>) {
+    if let Some(expires_at) = expires_at {
+        require!(
+            expires_at > current_time_ms(),
+            "Linkdrop must expire in the future"
+        );
+    }
+}
+
+/// Gas for `link_callback` to mint `num` tokens
+fn link_callback_gas(num: u16) -> Gas {
+    Gas(GAS_REQUIRED_FOR_LINKDROP.0 + GAS_PER_LINKDROP_TOKEN.0 * (num as u64 - 1))
+}
+
+/// Gas for the call that hands out what a linkdrop holds
+fn claim_tokens_gas(linkdrop: &LinkdropKey) -> Gas {
+    if linkdrop.escrowed_token.is_some() {
+        GAS_REQUIRED_FOR_LINKDROP
+    } else {
+        link_callback_gas(linkdrop.num)
+    }
+}
+
+/// Gas attached to `on_account_created`, which claims the tokens
+fn account_created_gas(linkdrop: &LinkdropKey) -> Gas {
+    Gas(claim_tokens_gas(linkdrop).0 + ON_CREATE_ACCOUNT_CALLBACK_GAS.0)
+}
+
+/// Whether the factory's `create_account`, the only promise before the callback,
+/// succeeded and returned `true`
+fn account_created() -> bool {
+    env::promise_results_count() == 1
+        && match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(false)
+            }
+            _ => false,
+        }
 }
 
 // Private methods
 impl Contract {
-    pub(crate) fn send(&mut self, public_key: PublicKey, mint_for_free: bool) -> Promise {
-        self.add_key(public_key, mint_for_free)
+    /// Charge the predecessor for a linkdrop of `num` tokens and add its key
+    pub(crate) fn reserve_linkdrop(
+        &mut self,
+        public_key: PublicKey,
+        num: u16,
+        expires_at: Option<TimestampMs>,
+    ) -> Promise {
+        require!(
+            num > 0 && num <= MAX_LINKDROP_TOKENS,
+            format!("Linkdrop must hold 1 to {} tokens", MAX_LINKDROP_TOKENS)
+        );
+        assert_expiry(expires_at);
+        let account = &env::predecessor_account_id();
+        let (total_cost, fee) = self.charge_for_linkdrops(account, num);
+        let linkdrop = LinkdropKey::new(
+            num,
+            self.is_owner(account),
+            account.clone(),
+            expires_at,
+            total_cost,
+            fee,
+            self.linkdrop_claim_deposit,
+        );
+        self.send(public_key.clone(), linkdrop)
+            .then(ext_self::on_send_with_callback(
+                public_key,
+                num,
+                env::current_account_id(),
+                total_cost,
+                GAS_REQUIRED_TO_CREATE_LINKDROP,
+            ))
     }
-    fn create_account(&self, new_account_id: AccountId, new_public_key: PublicKey) -> Promise {
+
+    /// Check that `account` can mint `num` linkdrop tokens, reserve them and refund any extra deposit.
+    /// Returns the total cost and the fee included in it.
+    fn charge_for_linkdrops(&mut self, account: &AccountId, num: u16) -> (Balance, Balance) {
+        let deposit = env::attached_deposit();
+        require!(
+            self.assert_can_mint(account, num) == num,
+            "Account doesn't have enough allowance for linkdrop"
+        );
+        let total_cost = self.cost_of_linkdrop(account).0 * num as Balance;
+        require!(
+            deposit >= total_cost,
+            "Not enough attached deposit to create linkdrop"
+        );
+        self.pending_tokens += num as u32;
+        self.use_whitelist_allowance(account, num);
+        log!("Total cost of creation is {}", total_cost);
+        refund(account, deposit - total_cost);
+        let fee = (self.full_link_price(account) - self.linkdrop_claim_deposit) * num as Balance;
+        (total_cost, fee)
+    }
+
+    /// Report which keys passed to `create_linkdrops` are skipped and why
+    fn check_new_linkdrop_keys(&self, public_keys: Vec<PublicKey>) -> Vec<LinkdropCreation> {
+        let mut seen = std::collections::HashSet::new();
+        public_keys
+            .into_iter()
+            .map(|public_key| {
+                let error = if !seen.insert(public_key.clone()) {
+                    Some("Duplicate key".to_string())
+                } else if self.is_known_key(&public_key) {
+                    Some("key already added".to_string())
+                } else {
+                    None
+                };
+                LinkdropCreation { public_key, error }
+            })
+            .collect()
+    }
+
+    pub(crate) fn send(&mut self, public_key: PublicKey, linkdrop: LinkdropKey) -> Promise {
+        self.add_key(public_key, linkdrop)
+    }
+    fn create_account(
+        &self,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+        deposit: Balance,
+    ) -> Promise {
         log!("creating account for {}", &new_account_id);
         ext_linkdrop::create_account(
             new_account_id,
             new_public_key,
-            self.get_linkdrop_contract(),
-            LINKDROP_DEPOSIT,
+            self.linkdrop_factory.clone(),
+            deposit,
             ON_CREATE_ACCOUNT_GAS,
         )
     }
 
-    fn add_key(&mut self, key: PublicKey, mint_for_free: bool) -> Promise {
-        // insert returns false if key was present
-        if self.accounts.insert(&key, &mint_for_free).is_some() {
+    fn add_key(&mut self, key: PublicKey, linkdrop: LinkdropKey) -> Promise {
+        self.record_linkdrop(&key, &linkdrop);
+        add_linkdrop_key(Promise::new(env::current_account_id()), key)
+    }
+
+    fn record_linkdrop(&mut self, key: &PublicKey, linkdrop: &LinkdropKey) {
+        if self.is_known_key(key) {
             env::panic_str("key already added");
         }
-        Promise::new(env::current_account_id()).add_access_key(
-            key,
-            ACCESS_KEY_ALLOWANCE,
-            env::current_account_id(),
-            "claim,create_account_and_claim".to_string(),
-        )
+        let previous = self.linkdrops.insert(key, linkdrop);
+        self.pending_linkdrops.insert(key);
+        let creator = &linkdrop.creator;
+        // A key that failed to be added is already listed for the creator who tried it
+        if previous.map_or(false, |previous| &previous.creator == creator) {
+            return;
+        }
+        let mut keys = self.linkdrops_by_creator.get(creator).unwrap_or_else(|| {
+            Vector::new(StorageKey::LinkdropsByCreator {
+                creator: creator.clone(),
+            })
+        });
+        keys.push(key);
+        self.linkdrops_by_creator.insert(creator, &keys);
     }
 
-    fn delete_current_access_key(&mut self) -> (bool, Promise) {
+    /// Call that hands out what a linkdrop holds once its key is deleted.
+    /// Minting is paid with what the creator paid for it.
+    fn claim_tokens(&self, account_id: &AccountId, linkdrop: &LinkdropKey) -> Promise {
+        match &linkdrop.escrowed_token {
+            Some(token_id) => ext_self::escrow_callback(
+                account_id.clone(),
+                token_id.clone(),
+                env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_REQUIRED_FOR_LINKDROP,
+            ),
+            None => ext_self::link_callback(
+                account_id.clone(),
+                linkdrop.mint_for_free,
+                linkdrop.num,
+                env::current_account_id(),
+                linkdrop.mint_payment(),
+                claim_tokens_gas(linkdrop),
+            ),
+        }
+    }
+
+    /// Mark a linkdrop `Claimed` and send its deposit to `deposit_to`
+    /// unless it already went to a created account
+    fn complete_claim(
+        &mut self,
+        key: &PublicKey,
+        mut linkdrop: LinkdropKey,
+        deposit_to: Option<AccountId>,
+    ) {
+        linkdrop.status = LinkdropStatus::Claimed;
+        self.linkdrops.insert(key, &linkdrop);
+        if let Some(account_id) = deposit_to.filter(|_| linkdrop.created_account.is_none()) {
+            refund(&account_id, linkdrop.deposit.0);
+        }
+    }
+
+    /// Settle a claim whose callbacks stopped before `on_claim`. If its tokens were handed out
+    /// the linkdrop is `Claimed` and its deposit refunded to the creator, otherwise its key is
+    /// added back. Returns whether it was claimed.
+    pub(crate) fn settle_stale_claim(&mut self, key: PublicKey) -> bool {
+        let linkdrop = self
+            .linkdrops
+            .get(&key)
+            .filter(|linkdrop| linkdrop.status == LinkdropStatus::Claiming)
+            .unwrap_or_else(|| env::panic_str("Linkdrop isn't being claimed"));
+        require!(
+            linkdrop
+                .claim_started_at
+                .map_or(true, |started_at| current_time_ms()
+                    >= started_at + STALE_CLAIM_MS),
+            "Claim may still be in progress"
+        );
+        if linkdrop.token_ids.is_empty() {
+            self.reopen_linkdrop(key, linkdrop);
+            return false;
+        }
+        let creator = linkdrop.creator.clone();
+        self.complete_claim(&key, linkdrop, Some(creator));
+        true
+    }
+
+    /// Give an escrowed token back to the linkdrop's creator
+    pub(crate) fn return_escrowed_token(&mut self, linkdrop: &LinkdropKey) {
+        if let Some(token_id) = &linkdrop.escrowed_token {
+            self.tokens.internal_transfer(
+                &env::current_account_id(),
+                &linkdrop.creator,
+                token_id,
+                None,
+                None,
+            );
+        }
+    }
+
+    /// Add back the key of a linkdrop whose claim failed, keeping any account it created
+    fn reopen_linkdrop(&mut self, key: PublicKey, linkdrop: LinkdropKey) -> Promise {
+        self.mark_pending(&key, linkdrop);
+        add_linkdrop_key(Promise::new(env::current_account_id()), key)
+    }
+
+    /// Make a linkdrop claimable again, without touching its key
+    fn mark_pending(&mut self, key: &PublicKey, mut linkdrop: LinkdropKey) {
+        linkdrop.status = LinkdropStatus::Pending;
+        linkdrop.claimer = None;
+        linkdrop.token_ids = vec![];
+        linkdrop.claim_started_at = None;
+        self.linkdrops.insert(key, &linkdrop);
+        self.pending_linkdrops.insert(key);
+    }
+
+    /// Start claiming the linkdrop of the key this tx is signed with and delete the key
+    fn delete_current_access_key(
+        &mut self,
+        claimer: &AccountId,
+    ) -> (PublicKey, LinkdropKey, Promise) {
         let key = env::signer_account_pk();
-        let mint_for_free = self.accounts.remove(&key);
-        require!(mint_for_free.is_some(), "Can't use a full access key.");
-        (
-            mint_for_free.unwrap(),
-            Promise::new(env::current_account_id()).delete_key(key),
-        )
+        let linkdrop = self
+            .close_linkdrop(&key, LinkdropStatus::Claiming, Some(claimer.clone()))
+            .unwrap_or_else(|| env::panic_str("Can't use a full access key."));
+        require!(!linkdrop.is_expired(), "Linkdrop has expired");
+        require!(
+            linkdrop
+                .created_account
+                .as_ref()
+                .map_or(true, |account_id| account_id == claimer),
+            "Linkdrop can only be claimed to the account created for it"
+        );
+        let promise = Promise::new(env::current_account_id()).delete_key(key.clone());
+        (key, linkdrop, promise)
+    }
+
+    /// Callbacks handing out a linkdrop's tokens only act while it is being claimed
+    pub(crate) fn assert_claiming(&self, key: &PublicKey) {
+        require!(
+            self.linkdrops
+                .get(key)
+                .map_or(false, |linkdrop| linkdrop.status
+                    == LinkdropStatus::Claiming),
+            "Linkdrop isn't being claimed"
+        );
+    }
+
+    /// Remove a linkdrop and its key, releasing its tokens and the creator's allowance
+    /// and refunding its creator
+    fn release_linkdrop(&mut self, key: &PublicKey) -> Promise {
+        let linkdrop = self
+            .close_linkdrop(key, LinkdropStatus::Revoked, None)
+            .unwrap();
+        self.pending_tokens -= linkdrop.num as u32;
+        self.restore_whitelist_allowance(&linkdrop.creator, linkdrop.num);
+        self.return_escrowed_token(&linkdrop);
+        refund(&linkdrop.creator, linkdrop.refund_amount());
+        Promise::new(env::current_account_id()).delete_key(key.clone())
+    }
+
+    /// Whether a key is or ever was a linkdrop, not counting keys that couldn't be added
+    fn is_known_key(&self, key: &PublicKey) -> bool {
+        self.linkdrops
+            .get(key)
+            .map_or(false, |linkdrop| linkdrop.status != LinkdropStatus::Failed)
+            || self.accounts.contains_key(key)
+    }
+
+    /// Pending linkdrop of a key, including keys created when each key held one token
+    fn get_linkdrop(&self, key: &PublicKey) -> Option<LinkdropKey> {
+        self.linkdrops
+            .get(key)
+            .filter(|linkdrop| linkdrop.status == LinkdropStatus::Pending)
+            .or_else(|| {
+                self.accounts.get(key).map(|mint_for_free| {
+                    let mint_payment = if mint_for_free { 0 } else { self.price() };
+                    LinkdropKey::legacy(mint_for_free, self.tokens.owner_id.clone(), mint_payment)
+                })
+            })
+    }
+
+    /// Move a pending linkdrop to `status`, keeping its record. Returns it as it was.
+    pub(crate) fn close_linkdrop(
+        &mut self,
+        key: &PublicKey,
+        status: LinkdropStatus,
+        claimer: Option<AccountId>,
+    ) -> Option<LinkdropKey> {
+        let linkdrop = self.get_linkdrop(key)?;
+        self.pending_linkdrops.remove(key);
+        self.accounts.remove(key);
+        self.linkdrops.insert(
+            key,
+            &LinkdropKey {
+                status,
+                claimer,
+                claim_started_at: (status == LinkdropStatus::Claiming).then(current_time_ms),
+                ..linkdrop.clone()
+            },
+        );
+        Some(linkdrop)
+    }
+
+    /// Record the tokens minted for a claimed linkdrop
+    pub(crate) fn record_linkdrop_tokens(&mut self, key: &PublicKey, tokens: &[Token]) {
+        if let Some(mut linkdrop) = self.linkdrops.get(key) {
+            linkdrop.token_ids = tokens.iter().map(|token| token.token_id.clone()).collect();
+            self.linkdrops.insert(key, &linkdrop);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_contract;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    const MS: u64 = 1_000_000;
+
+    fn context(predecessor: AccountId, now_ms: u64) -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .signer_account_id(predecessor.clone())
+            .predecessor_account_id(predecessor)
+            .block_timestamp(now_ms * MS)
+            .attached_deposit(parse_near!("2 N"));
+        context
+    }
+
+    fn setup() -> Contract {
+        setup_with_sale(Sale::default())
+    }
+
+    fn setup_with_sale(sale: Sale) -> Contract {
+        testing_env!(context(accounts(0), 0).build());
+        new_contract(sale)
+    }
+
+    /// Context of a callback from this contract, receiving `result` from the previous promise
+    fn callback(signer: AccountId, result: PromiseResult) {
+        let mut context = context(accounts(5), 0);
+        context.signer_account_id(signer);
+        callback_with(context, vec![result]);
+    }
+
+    /// Context of a claim's callback, signed with the linkdrop's key
+    fn claim_callback(public_key: PublicKey, result: PromiseResult) {
+        let mut context = context(accounts(5), 0);
+        context.signer_account_pk(public_key);
+        callback_with(context, vec![result]);
+    }
+
+    fn callback_with(context: VMContextBuilder, results: Vec<PromiseResult>) {
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            results,
+        );
+    }
+
+    fn key(i: u8) -> PublicKey {
+        let mut bytes = vec![0; 33];
+        bytes[1] = i;
+        PublicKey::try_from(bytes).unwrap()
+    }
+
+    #[test]
+    fn create_multi_token_linkdrop() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), Some(3), None);
+        assert_eq!(contract.linkdrop_tokens(key(1)), Some(3));
+        assert_eq!(contract.tokens_left(), 7);
+    }
+
+    #[test]
+    fn purchase_during_sale() {
+        let mut contract = setup_with_sale(Sale {
+            price: parse_near!("1 N").into(),
+            public_sale_start: Some(0),
+            ..Default::default()
+        });
+        testing_env!(context(accounts(1), 10).build());
+        let cost = contract.cost_of_linkdrop(&accounts(1)).0;
+        assert!(cost >= parse_near!("1 N") + LINKDROP_DEPOSIT + DEFAULT_LINKDROP_FEE);
+        contract.purchase_linkdrop(key(1), None, None);
+        assert_eq!(contract.linkdrop_tokens(key(1)), Some(1));
+        assert_eq!(contract.tokens_left(), 9);
+        assert_eq!(
+            contract.linkdrops.get(&key(1)).unwrap().mint_payment(),
+            parse_near!("1 N") + contract.token_storage_cost().0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "over mint limit")]
+    fn purchase_over_rate_limit() {
+        let mut contract = setup_with_sale(Sale {
+            public_sale_start: Some(0),
+            mint_rate_limit: Some(1),
+            ..Default::default()
+        });
+        testing_env!(context(accounts(1), 10).build());
+        contract.purchase_linkdrop(key(1), Some(2), None);
+    }
+
+    #[test]
+    fn revoke_restores_allowance() {
+        let mut contract = setup_with_sale(Sale {
+            public_sale_start: Some(0),
+            allowance: Some(2),
+            ..Default::default()
+        });
+        testing_env!(context(accounts(1), 10).build());
+        contract.purchase_linkdrop(key(1), Some(2), None);
+        assert_eq!(contract.remaining_allowance(&accounts(1), None), Some(0));
+        contract.revoke_linkdrop(key(1));
+        assert_eq!(contract.remaining_allowance(&accounts(1), None), Some(2));
+    }
+
+    #[test]
+    fn failed_purchase_restores_allowance() {
+        let mut contract = setup_with_sale(Sale {
+            public_sale_start: Some(0),
+            allowance: Some(2),
+            ..Default::default()
+        });
+        testing_env!(context(accounts(1), 10).build());
+        contract.purchase_linkdrop(key(1), Some(2), None);
+        callback(accounts(1), PromiseResult::Failed);
+        contract.on_send_with_callback(key(1), 2);
+        assert_eq!(contract.remaining_allowance(&accounts(1), None), Some(2));
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract currently closed")]
+    fn purchase_when_closed() {
+        let mut contract = setup();
+        testing_env!(context(accounts(1), 0).build());
+        contract.purchase_linkdrop(key(1), None, None);
+    }
+
+    #[test]
+    fn configurable_fee() {
+        let mut contract = setup();
+        let cost = contract.cost_of_linkdrop(&accounts(1)).0;
+        contract.update_linkdrop_fee(0.into());
+        assert_eq!(
+            contract.cost_of_linkdrop(&accounts(1)).0,
+            cost - DEFAULT_LINKDROP_FEE
+        );
+    }
+
+    /// Create a batch of linkdrops and run its callback with whether each key was added
+    fn create_batch_with(
+        contract: &mut Contract,
+        keys: Vec<PublicKey>,
+        added: Vec<bool>,
+    ) -> Vec<LinkdropCreation> {
+        contract.create_linkdrops(keys.clone(), None);
+        let promise_results = added
+            .into_iter()
+            .map(|added| {
+                if added {
+                    PromiseResult::Successful(vec![])
+                } else {
+                    PromiseResult::Failed
+                }
+            })
+            .collect();
+        callback_with(context(accounts(5), 0), promise_results);
+        let results = keys
+            .into_iter()
+            .map(|public_key| LinkdropCreation {
+                public_key,
+                error: None,
+            })
+            .collect();
+        contract.on_create_linkdrops(results)
+    }
+
+    #[test]
+    fn create_batch() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        let keys = vec![key(1), key(2), key(2), key(3)];
+        let results = contract.check_new_linkdrop_keys(keys.clone());
+        let errors: Vec<Option<&str>> = results.iter().map(|r| r.error.as_deref()).collect();
+        assert_eq!(
+            errors,
+            vec![Some("key already added"), None, Some("Duplicate key"), None]
+        );
+        contract.create_linkdrops(keys, None);
+        assert!(contract.check_key(key(2)) && contract.check_key(key(3)));
+        assert_eq!(contract.tokens_left(), 7);
+    }
+
+    #[test]
+    fn create_batch_of_known_keys() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        let results = contract.create_linkdrops(vec![key(1), key(1)], None);
+        assert!(matches!(results, PromiseOrValue::Value(results) if results.len() == 2));
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can create at most 50 linkdrops at once")]
+    fn batch_size_is_capped() {
+        let mut contract = setup();
+        let keys = (0..=MAX_LINKDROP_BATCH as u8).map(key).collect();
+        contract.create_linkdrops(keys, None);
+    }
+
+    #[test]
+    fn failed_keys_are_released_and_can_be_retried() {
+        let mut contract = setup();
+        let results = create_batch_with(
+            &mut contract,
+            vec![key(1), key(2), key(3)],
+            vec![true, false, true],
+        );
+        let errors: Vec<Option<&str>> = results.iter().map(|r| r.error.as_deref()).collect();
+        assert_eq!(errors, vec![None, Some("Failed to add key"), None]);
+        assert!(contract.check_key(key(1)) && contract.check_key(key(3)));
+        assert!(!contract.check_key(key(2)));
+        assert_eq!(
+            contract.linkdrop_info(key(2)).unwrap().status,
+            LinkdropStatus::Failed
+        );
+        assert_eq!(contract.tokens_left(), 8);
+
+        testing_env!(context(accounts(0), 0).build());
+        let results = create_batch_with(&mut contract, vec![key(2)], vec![true]);
+        assert_eq!(results[0].error, None);
+        assert!(contract.check_key(key(2)));
+        assert_eq!(contract.tokens_left(), 7);
+        assert_eq!(contract.linkdrop_count_by_creator(accounts(0)), 3);
+    }
+
+    #[test]
+    fn revoke_legacy_key_refunds_only_deposit() {
+        let mut contract = setup_with_sale(Sale {
+            price: parse_near!("1 N").into(),
+            ..Default::default()
+        });
+        contract.accounts.insert(&key(1), &false);
+        contract.pending_tokens += 1;
+        contract.revoke_linkdrop(key(1));
+        let linkdrop = contract.linkdrops.get(&key(1)).unwrap();
+        assert_eq!(linkdrop.status, LinkdropStatus::Revoked);
+        assert_eq!(linkdrop.refund_amount(), LINKDROP_DEPOSIT);
+        assert!(!contract.check_key(key(1)));
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    fn revoke_releases_tokens() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), Some(2), None);
+        contract.revoke_linkdrop(key(1));
+        assert!(!contract.check_key(key(1)));
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the creator, owner or admins can revoke a linkdrop")]
+    fn only_creator_revokes() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        testing_env!(context(accounts(1), 0).build());
+        contract.revoke_linkdrop(key(1));
+    }
+
+    #[test]
+    fn reclaim_expired() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, Some(100));
+        contract.create_linkdrop(key(2), None, Some(200));
+        contract.create_linkdrop(key(3), None, None);
+        testing_env!(context(accounts(1), 150).build());
+        assert_eq!(contract.reclaim_expired_linkdrops(None, 10), 1);
+        assert!(!contract.check_key(key(1)));
+        assert!(contract.check_key(key(2)));
+        assert_eq!(contract.tokens_left(), 8);
+
+        testing_env!(context(accounts(1), 1_000).build());
+        assert_eq!(contract.reclaim_expired_linkdrops(None, 10), 1);
+        assert!(contract.check_key(key(3)));
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    fn reclaim_expired_in_pages() {
+        let mut contract = setup();
+        (1..=4).for_each(|i| {
+            contract.create_linkdrop(key(i), None, Some(100));
+        });
+        testing_env!(context(accounts(1), 150).build());
+        assert_eq!(contract.reclaim_expired_linkdrops(Some(3), 10), 1);
+        assert_eq!(contract.reclaim_expired_linkdrops(Some(1), 2), 2);
+        assert_eq!(contract.reclaim_expired_linkdrops(None, 1), 1);
+        assert_eq!(contract.reclaim_expired_linkdrops(None, 10), 0);
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    /// Sign with the key of the linkdrop and start claiming it to `accounts(2)`
+    fn start_claim(contract: &mut Contract) {
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        contract.claim(accounts(2));
+    }
+
+    fn status(contract: &Contract) -> LinkdropStatus {
+        contract.linkdrop_info(key(1)).unwrap().status
+    }
+
+    #[test]
+    fn claim_succeeds() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), Some(2), None);
+        start_claim(&mut contract);
+        assert!(!contract.check_key(key(1)));
+        assert_eq!(status(&contract), LinkdropStatus::Claiming);
+        assert_eq!(
+            contract.linkdrop_info(key(1)).unwrap().claimer,
+            Some(accounts(2))
+        );
+
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        let tokens = contract.link_callback(accounts(2), true, 2);
+        let token_ids: Vec<TokenId> = tokens.into_iter().map(|t| t.token_id).collect();
+        assert_eq!(contract.linkdrop_info(key(1)).unwrap().token_ids, token_ids);
+        assert_eq!(contract.tokens_left(), 8);
+
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        assert!(contract.on_claim(key(1), Some(accounts(2))));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+    }
+
+    #[test]
+    fn failed_claim_reopens() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, Some(100));
+        start_claim(&mut contract);
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(!contract.on_claim(key(1), Some(accounts(2))));
+        let info = contract.linkdrop_info(key(1)).unwrap();
+        assert_eq!(info.status, LinkdropStatus::Pending);
+        assert_eq!(info.claimer, None);
+        assert!(contract.check_key(key(1)));
+        assert_eq!(contract.tokens_left(), 9);
+
+        testing_env!(context(accounts(1), 100).build());
+        assert_eq!(contract.reclaim_expired_linkdrops(None, 10), 1);
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    fn on_claim_is_idempotent() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        assert!(contract.on_claim(key(1), Some(accounts(2))));
+        assert!(!contract.on_claim(key(1), Some(accounts(2))));
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(!contract.on_claim(key(1), Some(accounts(2))));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+        assert!(!contract.check_key(key(1)));
+    }
+
+    #[test]
+    fn repeated_failure_adds_key_once() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(!contract.on_claim(key(1), None));
+        assert!(!contract.on_claim(key(1), None));
+        assert_eq!(status(&contract), LinkdropStatus::Pending);
+    }
+
+    #[test]
+    #[should_panic(expected = "Promise before Linkdrop callback failed")]
+    fn mint_after_failed_promise() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        claim_callback(key(1), PromiseResult::Failed);
+        contract.link_callback(accounts(2), true, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop isn't being claimed")]
+    fn mint_only_while_claiming() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        contract.link_callback(accounts(2), true, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop has expired")]
+    fn claim_after_expiry() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, Some(100));
+        testing_env!(context(accounts(5), 100).signer_account_pk(key(1)).build());
+        contract.claim(accounts(2));
+    }
+
+    /// Sign with the key of the linkdrop and start claiming it to a new `account_id`
+    fn start_account_claim(contract: &mut Contract, account_id: AccountId) {
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        contract.create_account_and_claim(account_id, key(9));
+    }
+
+    /// Delete the key of a claim started with `start_account_claim` and call the factory
+    fn delete_claim_key(contract: &mut Contract, account_id: AccountId) -> PromiseOrValue<bool> {
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        contract.on_claim_key_deleted(key(1), account_id, key(9))
+    }
+
+    /// Result of the factory's `create_account`
+    fn account_creation(created: &str) -> PromiseResult {
+        PromiseResult::Successful(created.as_bytes().to_vec())
+    }
+
+    fn is_value(result: PromiseOrValue<bool>, expected: bool) -> bool {
+        matches!(result, PromiseOrValue::Value(value) if value == expected)
+    }
+
+    #[test]
+    fn undeleted_key_creates_no_account() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_account_claim(&mut contract, accounts(2));
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(is_value(
+            contract.on_claim_key_deleted(key(1), accounts(2), key(9)),
+            false
+        ));
+        assert_eq!(status(&contract), LinkdropStatus::Pending);
+        assert!(contract.check_key(key(1)));
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    fn failed_account_creation_reopens() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_account_claim(&mut contract, accounts(2));
+        assert_eq!(status(&contract), LinkdropStatus::Claiming);
+        assert!(!is_value(
+            delete_claim_key(&mut contract, accounts(2)),
+            false
+        ));
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(is_value(
+            contract.on_account_created(key(1), accounts(2)),
+            false
+        ));
+        assert!(contract.check_key(key(1)));
+    }
+
+    #[test]
+    fn account_not_created_reopens() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_account_claim(&mut contract, accounts(2));
+        delete_claim_key(&mut contract, accounts(2));
+        claim_callback(key(1), account_creation("false"));
+        assert!(is_value(
+            contract.on_account_created(key(1), accounts(2)),
+            false
+        ));
+        let info = contract.linkdrop_info(key(1)).unwrap();
+        assert_eq!(info.status, LinkdropStatus::Pending);
+        assert!(contract.check_key(key(1)));
+        assert_eq!(contract.tokens_left(), 9);
+        assert!(contract
+            .linkdrops
+            .get(&key(1))
+            .unwrap()
+            .created_account
+            .is_none());
+    }
+
+    #[test]
+    fn created_account_claims_again() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_account_claim(&mut contract, accounts(2));
+        delete_claim_key(&mut contract, accounts(2));
+        claim_callback(key(1), account_creation("true"));
+        assert!(!is_value(
+            contract.on_account_created(key(1), accounts(2)),
+            false
+        ));
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(!contract.on_claim(key(1), None));
+        assert!(contract.check_key(key(1)));
+        assert_eq!(
+            contract.linkdrops.get(&key(1)).unwrap().created_account,
+            Some(accounts(2))
+        );
+
+        start_account_claim(&mut contract, accounts(2));
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        contract.link_callback(accounts(2), true, 1);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        assert!(contract.on_claim(key(1), None));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop can only be claimed to the account created for it")]
+    fn created_account_is_the_only_claimer() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_account_claim(&mut contract, accounts(2));
+        delete_claim_key(&mut contract, accounts(2));
+        claim_callback(key(1), account_creation("true"));
+        contract.on_account_created(key(1), accounts(2));
+        claim_callback(key(1), PromiseResult::Failed);
+        contract.on_claim(key(1), None);
+        start_account_claim(&mut contract, accounts(3));
+    }
+
+    #[test]
+    fn resolve_stale_claim() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        testing_env!(context(accounts(0), STALE_CLAIM_MS).build());
+        assert!(!contract.resolve_stale_linkdrop(key(1)));
+        assert_eq!(status(&contract), LinkdropStatus::Pending);
+        assert!(contract.check_key(key(1)));
+        contract.revoke_linkdrop(key(1));
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    fn resolve_stale_minted_claim() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        contract.link_callback(accounts(2), true, 1);
+        testing_env!(context(accounts(0), STALE_CLAIM_MS).build());
+        assert!(contract.resolve_stale_linkdrop(key(1)));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Claim may still be in progress")]
+    fn resolve_recent_claim() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        start_claim(&mut contract);
+        testing_env!(context(accounts(0), STALE_CLAIM_MS - 1).build());
+        contract.resolve_stale_linkdrop(key(1));
+    }
+
+    #[test]
+    fn list_by_creator() {
+        let mut contract = setup();
+        contract.create_linkdrops(vec![key(1), key(2), key(3)], None);
+        contract.revoke_linkdrop(key(2));
+        assert_eq!(contract.linkdrop_count_by_creator(accounts(0)), 3);
+        assert_eq!(contract.linkdrop_count_by_creator(accounts(1)), 0);
+        let infos = contract.linkdrops_by_creator(accounts(0), Some(1), Some(2));
+        let keys: Vec<&PublicKey> = infos.iter().map(|info| &info.public_key).collect();
+        assert_eq!(keys, vec![&key(2), &key(3)]);
+        assert_eq!(infos[0].status, LinkdropStatus::Revoked);
+        assert_eq!(infos[1].status, LinkdropStatus::Pending);
+    }
+
+    #[test]
+    #[should_panic(expected = "key already added")]
+    fn revoked_key_is_not_reused() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        contract.revoke_linkdrop(key(1));
+        contract.create_linkdrop(key(1), None, None);
+    }
+
+    #[test]
+    fn configurable_claim_deposit() {
+        let mut contract = setup();
+        let cost = contract.cost_of_linkdrop(&accounts(1)).0;
+        contract.update_linkdrop_claim_deposit(0.into());
+        assert_eq!(
+            contract.cost_of_linkdrop(&accounts(1)).0,
+            cost - LINKDROP_DEPOSIT
+        );
+        contract.create_linkdrop(key(1), None, None);
+        assert_eq!(contract.linkdrop_claim_deposit().0, 0);
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        assert_eq!(contract.get_key_balance().0, 0);
+    }
+
+    #[test]
+    fn configurable_factory() {
+        let mut contract = setup();
+        assert_eq!(contract.get_linkdrop_contract(), default_linkdrop_factory());
+        contract.update_linkdrop_factory(accounts(3));
+        assert_eq!(contract.get_linkdrop_contract(), accounts(3));
+    }
+
+    #[test]
+    fn claim_existing_account() {
+        let mut contract = setup();
+        contract.create_linkdrop(key(1), None, None);
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        contract.claim_existing(accounts(2));
+        assert_eq!(status(&contract), LinkdropStatus::Claiming);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        contract.link_callback(accounts(2), true, 1);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        assert!(contract.on_claim(key(1), Some(accounts(0))));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    /// A linkdrop created by `accounts(1)` for a token it owns
+    fn setup_token_linkdrop() -> (Contract, TokenId) {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        let token_id = contract.nft_token_by_mint_index(0).unwrap().token_id;
+        testing_env!(context(accounts(1), 0).build());
+        contract.create_token_linkdrop(key(1), token_id.clone(), None);
+        (contract, token_id)
+    }
+
+    fn owner_of(contract: &Contract, token_id: &TokenId) -> AccountId {
+        contract.nft_token(token_id.clone()).unwrap().owner_id
+    }
+
+    #[test]
+    fn token_linkdrop_escrows_token() {
+        let (contract, token_id) = setup_token_linkdrop();
+        assert_eq!(owner_of(&contract, &token_id), accounts(5));
+        let info = contract.linkdrop_info(key(1)).unwrap();
+        assert_eq!(info.escrowed_token, Some(token_id));
+        assert_eq!(info.num, 0);
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    fn claim_token_linkdrop() {
+        let (mut contract, token_id) = setup_token_linkdrop();
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        contract.claim_existing(accounts(2));
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        let token = contract.escrow_callback(accounts(2), token_id.clone());
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(
+            contract.linkdrop_info(key(1)).unwrap().token_ids,
+            vec![token_id]
+        );
+        assert_eq!(contract.tokens_left(), 9);
+        claim_callback(key(1), PromiseResult::Successful(vec![]));
+        assert!(contract.on_claim(key(1), Some(accounts(1))));
+        assert_eq!(status(&contract), LinkdropStatus::Claimed);
+    }
+
+    #[test]
+    fn failed_token_claim_keeps_escrow() {
+        let (mut contract, token_id) = setup_token_linkdrop();
+        testing_env!(context(accounts(5), 0).signer_account_pk(key(1)).build());
+        contract.claim_existing(accounts(2));
+        claim_callback(key(1), PromiseResult::Failed);
+        assert!(!contract.on_claim(key(1), Some(accounts(1))));
+        assert_eq!(owner_of(&contract, &token_id), accounts(5));
+        assert!(contract.check_key(key(1)));
+        testing_env!(context(accounts(1), 0).build());
+        contract.revoke_linkdrop(key(1));
+        assert_eq!(owner_of(&contract, &token_id), accounts(1));
+    }
+
+    #[test]
+    fn revoke_returns_token() {
+        let (mut contract, token_id) = setup_token_linkdrop();
+        contract.revoke_linkdrop(key(1));
+        assert_eq!(owner_of(&contract, &token_id), accounts(1));
+        assert_eq!(contract.tokens_left(), 9);
+    }
+
+    #[test]
+    fn failed_token_linkdrop_returns_token() {
+        let (mut contract, token_id) = setup_token_linkdrop();
+        callback(accounts(1), PromiseResult::Failed);
+        contract.on_send_with_callback(key(1), 0);
+        assert_eq!(owner_of(&contract, &token_id), accounts(1));
+        assert!(!contract.check_key(key(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token's owner can create a linkdrop for it")]
+    fn only_owner_escrows_token() {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        let token_id = contract.nft_token_by_mint_index(0).unwrap().token_id;
+        testing_env!(context(accounts(2), 0).build());
+        contract.create_token_linkdrop(key(1), token_id, None);
     }
 }
@@ -0,0 +1,381 @@
+use crate::*;
+use raffle_collection::RaffleCollection;
+
+/// Registration window of a presale lottery and what its winners get
+#[witgen]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LotteryConfig {
+    pub registration_start: TimestampMs,
+    pub registration_end: TimestampMs,
+    /// Most entrants that can win
+    pub winners: u32,
+    /// Presale allowance given to each winner
+    pub allowance: u16,
+}
+
+/// Outcome of the lottery for an account
+#[witgen]
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum LotteryResult {
+    NotRegistered,
+    /// Registered, but the draw isn't finished
+    Pending,
+    Won,
+    Lost,
+}
+
+/// Entrants of a presale lottery and the draw of its winners
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Lottery {
+    config: LotteryConfig,
+    entrants: Vector<AccountId>,
+    /// Whether each entrant has won so far
+    results: LookupMap<AccountId, bool>,
+    /// Created once registration closes and the first batch is drawn
+    draw: Option<RaffleCollection>,
+    /// Number of lotteries started before this one, which keeps their storage apart
+    round: u32,
+}
+
+impl Lottery {
+    fn is_open(&self) -> bool {
+        let now = current_time_ms();
+        self.config.registration_start <= now && now < self.config.registration_end
+    }
+
+    fn is_done(&self) -> bool {
+        self.draw.as_ref().map_or(false, RaffleCollection::is_done)
+    }
+
+    fn result(&self, account_id: &AccountId) -> LotteryResult {
+        match self.results.get(account_id) {
+            None => LotteryResult::NotRegistered,
+            Some(true) => LotteryResult::Won,
+            Some(false) if self.is_done() => LotteryResult::Lost,
+            Some(false) => LotteryResult::Pending,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Open a lottery that entrants register for instead of being added to the whitelist.
+    /// Once every winner of the current lottery is drawn it can be replaced by a new one;
+    /// its winners keep their whitelist allowance.
+    /// @allow ["::admins", "::owner"]
+    pub fn start_lottery(&mut self, config: LotteryConfig) -> bool {
+        self.assert_owner_or_admin();
+        let round = match &self.lottery {
+            None => 0,
+            Some(lottery) => {
+                require!(lottery.is_done(), "Current lottery hasn't been drawn");
+                lottery.round + 1
+            }
+        };
+        require!(
+            config.registration_start < config.registration_end,
+            "Registration must end after it starts"
+        );
+        require!(
+            config.winners > 0 && config.allowance > 0,
+            "Lottery must have winners with an allowance"
+        );
+        self.lottery = Some(Lottery {
+            config,
+            entrants: Vector::new(StorageKey::LotteryEntrants { round }),
+            results: LookupMap::new(StorageKey::LotteryResults { round }),
+            draw: None,
+            round,
+        });
+        true
+    }
+
+    /// Enter the lottery while registration is open. Attached deposit must cover the storage used.
+    #[payable]
+    pub fn register_for_lottery(&mut self) -> u32 {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let lottery = self.get_lottery_mut();
+        require!(lottery.is_open(), "Lottery registration is closed");
+        require!(
+            lottery.results.insert(&account_id, &false).is_none(),
+            "Already registered"
+        );
+        lottery.entrants.push(&account_id);
+        let count = lottery.entrants.len() as u32;
+        let storage_used = env::storage_usage() - initial_storage_usage;
+        refund_deposit_to_account(storage_used, account_id);
+        count
+    }
+
+    /// Draw up to `max_winners` more winners once registration has closed.
+    /// Winners are added to the whitelist with the lottery's allowance.
+    /// @allow ["::admins", "::owner"]
+    pub fn draw_lottery(&mut self, max_winners: u32) -> Vec<AccountId> {
+        self.assert_owner_or_admin();
+        let mut lottery = self
+            .lottery
+            .take()
+            .unwrap_or_else(|| env::panic_str("No lottery"));
+        require!(
+            current_time_ms() >= lottery.config.registration_end,
+            "Lottery registration hasn't closed"
+        );
+        let mut draw = lottery.draw.take().unwrap_or_else(|| {
+            RaffleCollection::new(
+                StorageKey::LotteryDraw {
+                    round: lottery.round,
+                },
+                lottery.entrants.len() as u32,
+                lottery.config.winners,
+            )
+        });
+        let account_id = env::predecessor_account_id();
+        let mut winners = vec![];
+        for i in 0..max_winners {
+            if draw.is_done() {
+                break;
+            }
+            let random = self.next_random(&account_id, i as u16);
+            let index = draw.draw(random).unwrap();
+            let winner = lottery.entrants.get(index as u64).unwrap();
+            lottery.results.insert(&winner, &true);
+            let allowance = self
+                .whitelist
+                .get(&winner)
+                .unwrap_or_else(|| Allowance::new(lottery.config.allowance))
+                .raise_max(lottery.config.allowance);
+            self.whitelist.insert(&winner, &allowance);
+            winners.push(winner);
+        }
+        lottery.draw = Some(draw);
+        self.lottery = Some(lottery);
+        winners
+    }
+
+    /// Configuration of the lottery, if one was started
+    pub fn lottery_config(&self) -> Option<LotteryConfig> {
+        self.lottery.as_ref().map(|lottery| lottery.config.clone())
+    }
+
+    /// Number of accounts registered for the lottery
+    pub fn lottery_registration_count(&self) -> u32 {
+        self.lottery
+            .as_ref()
+            .map_or(0, |lottery| lottery.entrants.len() as u32)
+    }
+
+    /// Paginated list of accounts registered for the lottery, in order of registration
+    pub fn lottery_entrants(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+        self.lottery.as_ref().map_or_else(Vec::new, |lottery| {
+            lottery
+                .entrants
+                .iter()
+                .skip(from_index.unwrap_or(0) as usize)
+                .take(limit.unwrap_or(u64::MAX) as usize)
+                .collect()
+        })
+    }
+
+    /// Paginated list of winners, in the order they were drawn
+    pub fn lottery_winners(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<AccountId> {
+        self.lottery
+            .as_ref()
+            .and_then(|lottery| {
+                let winners = lottery.draw.as_ref()?.get_winners(from_index, limit);
+                Some(
+                    winners
+                        .into_iter()
+                        .filter_map(|index| lottery.entrants.get(index as u64))
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether an account registered, and if so whether it won
+    pub fn lottery_result(&self, account_id: AccountId) -> LotteryResult {
+        self.lottery
+            .as_ref()
+            .map_or(LotteryResult::NotRegistered, |lottery| {
+                lottery.result(&account_id)
+            })
+    }
+
+    /// Whether every winner has been drawn
+    pub fn is_lottery_drawn(&self) -> bool {
+        self.lottery.as_ref().map_or(false, Lottery::is_done)
+    }
+}
+
+impl Contract {
+    fn get_lottery_mut(&mut self) -> &mut Lottery {
+        self.lottery
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("No lottery"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_contract;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    const MS: u64 = 1_000_000;
+
+    fn context(predecessor: AccountId, now_ms: u64) -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .signer_account_id(predecessor.clone())
+            .predecessor_account_id(predecessor)
+            .block_timestamp(now_ms * MS)
+            .attached_deposit(parse_near!("1 N"));
+        context
+    }
+
+    fn setup(winners: u32) -> Contract {
+        testing_env!(context(accounts(0), 0).build());
+        let mut contract = new_contract(Sale::default());
+        contract.start_lottery(LotteryConfig {
+            registration_start: 100,
+            registration_end: 200,
+            winners,
+            allowance: 2,
+        });
+        contract
+    }
+
+    fn register(contract: &mut Contract, account_id: AccountId, now_ms: u64) -> u32 {
+        testing_env!(context(account_id, now_ms).build());
+        contract.register_for_lottery()
+    }
+
+    fn draw(contract: &mut Contract, max_winners: u32) -> Vec<AccountId> {
+        testing_env!(context(accounts(0), 200).build());
+        contract.draw_lottery(max_winners)
+    }
+
+    #[test]
+    fn draw_in_batches() {
+        let mut contract = setup(3);
+        (1..6).for_each(|i| {
+            register(&mut contract, accounts(i), 150);
+        });
+        assert_eq!(contract.lottery_registration_count(), 5);
+        assert_eq!(
+            contract.lottery_entrants(Some(1), Some(2)),
+            vec![accounts(2), accounts(3)]
+        );
+
+        let mut winners = draw(&mut contract, 2);
+        assert_eq!(winners.len(), 2);
+        assert!(!contract.is_lottery_drawn());
+        assert_eq!(
+            contract.lottery_result(winners[0].clone()),
+            LotteryResult::Won
+        );
+
+        winners.extend(draw(&mut contract, 2));
+        assert_eq!(winners.len(), 3);
+        assert_eq!(contract.lottery_winners(None, None), winners);
+        assert!(contract.is_lottery_drawn());
+        assert!(draw(&mut contract, 2).is_empty());
+
+        (1..6).map(accounts).for_each(|account_id| {
+            let won = winners.contains(&account_id);
+            let expected = if won {
+                LotteryResult::Won
+            } else {
+                LotteryResult::Lost
+            };
+            assert_eq!(contract.lottery_result(account_id.clone()), expected);
+            assert_eq!(
+                contract.remaining_allowance(&account_id, None),
+                won.then(|| 2)
+            );
+        });
+        assert_eq!(
+            contract.lottery_result(accounts(0)),
+            LotteryResult::NotRegistered
+        );
+    }
+
+    #[test]
+    fn more_winners_than_entrants() {
+        let mut contract = setup(5);
+        register(&mut contract, accounts(1), 100);
+        assert_eq!(contract.lottery_result(accounts(1)), LotteryResult::Pending);
+        assert_eq!(draw(&mut contract, 5), vec![accounts(1)]);
+        assert!(contract.is_lottery_drawn());
+    }
+
+    #[test]
+    #[should_panic(expected = "Lottery registration is closed")]
+    fn register_after_window() {
+        let mut contract = setup(1);
+        register(&mut contract, accounts(1), 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "Already registered")]
+    fn register_twice() {
+        let mut contract = setup(1);
+        register(&mut contract, accounts(1), 150);
+        register(&mut contract, accounts(1), 160);
+    }
+
+    #[test]
+    fn start_another_lottery_once_drawn() {
+        let mut contract = setup(1);
+        register(&mut contract, accounts(1), 150);
+        assert_eq!(draw(&mut contract, 1), vec![accounts(1)]);
+
+        testing_env!(context(accounts(0), 200).build());
+        contract.start_lottery(LotteryConfig {
+            registration_start: 300,
+            registration_end: 400,
+            winners: 1,
+            allowance: 3,
+        });
+        assert_eq!(contract.lottery_registration_count(), 0);
+        assert!(contract.lottery_winners(None, None).is_empty());
+        assert_eq!(
+            contract.lottery_result(accounts(1)),
+            LotteryResult::NotRegistered
+        );
+        assert_eq!(contract.remaining_allowance(&accounts(1), None), Some(2));
+
+        register(&mut contract, accounts(2), 300);
+        testing_env!(context(accounts(0), 400).build());
+        assert_eq!(contract.draw_lottery(1), vec![accounts(2)]);
+        assert_eq!(contract.remaining_allowance(&accounts(2), None), Some(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Current lottery hasn't been drawn")]
+    fn start_lottery_before_draw() {
+        let mut contract = setup(1);
+        register(&mut contract, accounts(1), 150);
+        testing_env!(context(accounts(0), 200).build());
+        contract.start_lottery(LotteryConfig {
+            registration_start: 300,
+            registration_end: 400,
+            winners: 1,
+            allowance: 2,
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Lottery registration hasn't closed")]
+    fn draw_during_registration() {
+        let mut contract = setup(1);
+        register(&mut contract, accounts(1), 150);
+        testing_env!(context(accounts(0), 150).build());
+        contract.draw_lottery(1);
+    }
+}
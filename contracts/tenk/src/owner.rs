@@ -1,5 +1,10 @@
 use crate::*;
 
+/// Upper bound of the gas used to mint one token in `airdrop_mint`
+const GAS_PER_AIRDROP_TOKEN: Gas = Gas(parse_gas!("8 Tgas") as u64);
+/// Gas left over to finish an `airdrop_mint` call after the last recipient
+const GAS_RESERVED_FOR_AIRDROP: Gas = Gas(parse_gas!("10 Tgas") as u64);
+
 #[near_bindgen]
 impl Contract {
     // Owner private methods
@@ -38,16 +43,131 @@ impl Contract {
         true
     }
 
+    /// Update any part of the sale in one call. Fields missing from `patch` are left unchanged.
+    /// The resulting sale is validated as a whole before being saved.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_sale(&mut self, patch: SalePatch) -> Sale {
+        self.assert_owner_or_admin();
+        let mut sale = self.sale.clone();
+        sale.apply(patch);
+        sale.validate();
+        sale.validate_schedule();
+        self.sale = sale.clone();
+        sale
+    }
+
     /// @allow ["::admins", "::owner"]
     pub fn update_uri(&mut self, uri: String) -> bool {
         self.assert_owner_or_admin();
+        self.assert_metadata_not_frozen();
         let mut metadata = self.metadata.get().unwrap();
         log!("New URI: {}", &uri);
         metadata.base_uri = Some(uri);
         self.metadata.set(&metadata);
+        log_contract_metadata_update();
+        true
+    }
+
+    /// Use `base_uri` for tokens with raffle indexes in `start..end` minted from now on.
+    /// Their `media` and `reference` will be absolute URLs.
+    /// @allow ["::admins", "::owner"]
+    pub fn add_base_uri_range(&mut self, start: u64, end: u64, base_uri: String) -> bool {
+        self.assert_owner_or_admin();
+        self.assert_metadata_not_frozen();
+        require!(start < end, "range start must be before its end");
+        let range = BaseUriRange {
+            start,
+            end,
+            base_uri,
+        };
+        require!(
+            !self
+                .base_uri_ranges
+                .iter()
+                .any(|other| other.overlaps(&range)),
+            "range overlaps an existing range"
+        );
+        self.base_uri_ranges.push(range);
+        true
+    }
+
+    /// Remove the base URI range starting at `start`
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_base_uri_range(&mut self, start: u64) -> bool {
+        self.assert_owner_or_admin();
+        self.assert_metadata_not_frozen();
+        let len = self.base_uri_ranges.len();
+        self.base_uri_ranges.retain(|range| range.start != start);
+        len != self.base_uri_ranges.len()
+    }
+
+    /// Update any part of the contract metadata. Fields missing from `patch` are left unchanged.
+    /// Passing `freeze` locks the metadata permanently after this update.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_contract_metadata(
+        &mut self,
+        patch: ContractMetadataPatch,
+        freeze: Option<bool>,
+    ) -> NFTContractMetadata {
+        self.assert_owner_or_admin();
+        self.assert_metadata_not_frozen();
+        let mut metadata = self.metadata.get().unwrap();
+        patch.apply(&mut metadata);
+        metadata.assert_valid();
+        self.metadata.set(&metadata);
+        log_contract_metadata_update();
+        if freeze.unwrap_or(false) {
+            log!("Contract metadata is now frozen");
+            self.metadata_frozen = true;
+        }
+        metadata
+    }
+
+    /// Replace the templates used to build token metadata.
+    /// Can only be changed before any token is minted or reserved.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_metadata_template(&mut self, template: MetadataTemplate) -> bool {
+        self.assert_owner_or_admin();
+        self.assert_nothing_minted();
+        self.metadata_template = template;
+        true
+    }
+
+    /// Replace the weighted traits drawn for each token.
+    /// Can only be changed before any token is minted or reserved.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_traits(&mut self, traits: Vec<TraitCategory>) -> bool {
+        self.assert_owner_or_admin();
+        self.assert_nothing_minted();
+        self.traits = TraitTable::new(traits);
         true
     }
 
+    /// Load metadata for tokens that haven't been minted yet. Can be called in batches.
+    /// Loaded fields are used instead of the ones generated from the template when the token is minted.
+    /// @allow ["::owner"]
+    pub fn load_token_metadata(&mut self, entries: HashMap<TokenId, TokenMetadataOverride>) -> u32 {
+        self.assert_owner();
+        entries.into_iter().for_each(|(token_id, entry)| {
+            require!(
+                self.token_id_format
+                    .parse(&token_id)
+                    .map_or(false, |index| index < self.raffle.total()
+                        && !self.removed_tokens.contains(&index)),
+                format!("Token {} is not in the pool", token_id)
+            );
+            require!(
+                self.tokens.owner_by_id.get(&token_id).is_none(),
+                format!("Token {} is already minted", token_id)
+            );
+            entry.validate();
+            if self.token_metadata.insert(&token_id, &entry).is_none() {
+                self.token_metadata_loaded += 1;
+            }
+        });
+        self.token_metadata_loaded
+    }
+
     /// Add whitelist accounts at a given max allowance
     /// @allow ["::admins", "::owner"]
     pub fn add_whitelist_accounts(
@@ -117,6 +237,7 @@ impl Contract {
         if presale_price.is_some() {
             self.sale.presale_price = presale_price;
         }
+        self.sale.validate_schedule();
         true
     }
 
@@ -128,6 +249,131 @@ impl Contract {
         if let Some(price) = price {
             self.sale.price = price
         }
+        self.sale.validate_schedule();
+        true
+    }
+
+    /// Add `num` new tokens to the pool, with ids after the highest id so far
+    /// @allow ["::owner"]
+    pub fn extend_supply(&mut self, num: u32) -> u32 {
+        self.assert_owner();
+        self.raffle.extend(num as u64);
+        self.tokens_left()
+    }
+
+    /// Remove `num` tokens from the pool so that they can never be minted,
+    /// e.g. to burn the rest of an unsold collection.
+    /// `Highest` only works until a token is drawn randomly (see `can_reduce_highest_supply`),
+    /// so after a random sale has started only `Random` can be used.
+    /// @allow ["::owner"]
+    pub fn reduce_supply(&mut self, num: u32, strategy: SupplyReduction) -> u32 {
+        self.assert_owner();
+        require!(
+            num <= self.tokens_left(),
+            "Can't remove more tokens than are left"
+        );
+        require!(
+            self.mint_order == MintOrder::Random || matches!(strategy, SupplyReduction::Highest),
+            "Only the highest tokens can be removed when minting in order"
+        );
+        require!(
+            self.raffle.is_ordered() || matches!(strategy, SupplyReduction::Random),
+            "Tokens were drawn randomly, so the highest left can't be removed"
+        );
+        require!(
+            matches!(strategy, SupplyReduction::Highest) || num <= u16::MAX as u32,
+            "Can't randomly remove more than 65535 tokens at once"
+        );
+        let account_id = env::predecessor_account_id();
+        for i in 0..num {
+            let index = match strategy {
+                SupplyReduction::Random => {
+                    let random = self.next_random(&account_id, i as u16);
+                    self.raffle.draw(random)
+                }
+                SupplyReduction::Highest => self.raffle.pop().unwrap(),
+            };
+            self.removed_tokens.insert(&index);
+            if self
+                .token_metadata
+                .remove(&self.token_id_format.format(index))
+                .is_some()
+            {
+                self.token_metadata_loaded -= 1;
+            }
+        }
+        self.tokens_left()
+    }
+
+    /// Mint `num` tokens from the raffle to each recipient, emitting one `nft_mint` per batch.
+    /// Stops before running out of gas, possibly partway through a recipient's tokens, and returns
+    /// where it stopped, which can be passed back as `from` to resume. Returns `None` once all are
+    /// minted to.
+    /// @allow ["::owner"]
+    pub fn airdrop_mint(
+        &mut self,
+        recipients: Vec<(AccountId, u16)>,
+        from: Option<AirdropCursor>,
+    ) -> Option<AirdropCursor> {
+        self.assert_owner();
+        let from = from.unwrap_or_default();
+        let start = from.recipient as usize;
+        require!(
+            start <= recipients.len(),
+            "Cursor is past the last recipient"
+        );
+        require!(
+            recipients
+                .get(start)
+                .map_or(from.minted == 0, |(_, num)| from.minted <= *num),
+            "Cursor is past the recipient's tokens"
+        );
+        let remaining: u32 = recipients[start..]
+            .iter()
+            .map(|(_, num)| *num as u32)
+            .sum::<u32>()
+            - from.minted as u32;
+        require!(
+            remaining <= self.tokens_left(),
+            "Not enough tokens left for airdrop"
+        );
+        let mut minted = from.minted;
+        let mut progressed = false;
+        for (index, (owner_id, num)) in recipients.iter().enumerate().skip(start) {
+            let left = num - minted;
+            let gas_left = env::prepaid_gas().0 - env::used_gas().0;
+            let fits =
+                gas_left.saturating_sub(GAS_RESERVED_FOR_AIRDROP.0) / GAS_PER_AIRDROP_TOKEN.0;
+            let batch = fits.min(left as u64) as u16;
+            if batch > 0 {
+                self.nft_mint_many_ungaurded(batch, owner_id, true);
+                progressed = true;
+            }
+            if batch < left {
+                require!(progressed, "Not enough gas to mint the next token");
+                return Some(AirdropCursor {
+                    recipient: index as u32,
+                    minted: minted + batch,
+                });
+            }
+            minted = 0;
+        }
+        None
+    }
+
+    /// Switch between handing out tokens randomly or in order.
+    /// Can only be changed before any token is minted or reserved.
+    /// @allow ["::admins", "::owner"]
+    pub fn update_mint_order(&mut self, mint_order: MintOrder) -> bool {
+        self.assert_owner_or_admin();
+        self.assert_nothing_minted();
+        if mint_order == MintOrder::Sequential {
+            require!(
+                self.raffle.is_ordered(),
+                "Tokens were already removed out of order"
+            );
+        }
+        self.mint_order = mint_order;
         true
     }
 
@@ -139,12 +385,52 @@ impl Contract {
         true
     }
 
+    /// Allow an account, e.g. a game contract, to update the metadata of minted tokens
+    /// @allow ["::admins", "::owner"]
+    pub fn add_metadata_updater(&mut self, account_id: AccountId) -> bool {
+        self.assert_owner_or_admin();
+        self.metadata_updaters.insert(&account_id)
+    }
+
+    /// @allow ["::admins", "::owner"]
+    pub fn remove_metadata_updater(&mut self, account_id: AccountId) -> bool {
+        self.assert_owner_or_admin();
+        self.metadata_updaters.remove(&account_id)
+    }
+
+    /// Update the fee charged on top of the token price for each linkdrop.
+    /// Careful this is in yoctoNear: 1N = 1000000000000000000000000 yN
+    /// @allow ["::owner"]
+    pub fn update_linkdrop_fee(&mut self, fee: U128) -> bool {
+        self.assert_owner();
+        self.linkdrop_fee = fee.0;
+        true
+    }
+
+    /// Update the account that creates new accounts for linkdrop claims
+    /// @allow ["::owner"]
+    pub fn update_linkdrop_factory(&mut self, account_id: AccountId) -> bool {
+        self.assert_owner();
+        self.linkdrop_factory = account_id;
+        true
+    }
+
+    /// Update the NEAR sent to the account claiming a linkdrop. Applies to linkdrops created after.
+    /// Careful this is in yoctoNear: 1N = 1000000000000000000000000 yN
+    /// @allow ["::owner"]
+    pub fn update_linkdrop_claim_deposit(&mut self, amount: U128) -> bool {
+        self.assert_owner();
+        self.linkdrop_claim_deposit = amount.0;
+        true
+    }
+
     /// Update public sale price.
     /// Careful this is in yoctoNear: 1N = 1000000000000000000000000 yN
     /// @allow ["::admins", "::owner"]
     pub fn update_price(&mut self, price: U128) -> bool {
         self.assert_owner_or_admin();
         self.sale.price = price;
+        self.sale.validate_schedule();
         true
     }
 
@@ -154,6 +440,7 @@ impl Contract {
     pub fn update_presale_price(&mut self, presale_price: Option<U128>) -> bool {
         self.assert_owner_or_admin();
         self.sale.presale_price = presale_price;
+        self.sale.validate_schedule();
         true
     }
 
@@ -163,6 +450,7 @@ impl Contract {
     pub fn update_presale_start(&mut self, presale_start: TimestampMs) -> bool {
         self.assert_owner_or_admin();
         self.sale.presale_start = Some(presale_start);
+        self.sale.validate_schedule();
         true
     }
 
@@ -172,28 +460,392 @@ impl Contract {
     pub fn update_public_sale_start(&mut self, public_sale_start: TimestampMs) -> bool {
         self.assert_owner_or_admin();
         self.sale.public_sale_start = Some(public_sale_start);
+        self.sale.validate_schedule();
         true
     }
 
     #[payable]
-    /// Create a pending token that can be claimed with corresponding private key
-    /// @allow ["::admins", "::owner"]
-    pub fn create_linkdrop(&mut self, public_key: PublicKey) -> Promise {
-        self.assert_owner_or_admin();
-        let deposit = env::attached_deposit();
-        let account = &env::predecessor_account_id();
-        self.assert_can_mint(account, 1);
-        let total_cost = self.cost_of_linkdrop(account).0;
-        self.pending_tokens += 1;
-        let mint_for_free = self.is_owner(account);
-        self.use_whitelist_allowance(account, 1);
-        log!("Total cost of creation is {}", total_cost);
-        refund(account, deposit - total_cost);
-        self.send(public_key, mint_for_free)
-            .then(ext_self::on_send_with_callback(
-                env::current_account_id(),
-                total_cost,
-                GAS_REQUIRED_TO_CREATE_LINKDROP,
-            ))
+    /// Create pending tokens that can be claimed with corresponding private key, one by default.
+    /// Costs `cost_of_linkdrop` for each token. Once `expires_at` passes the linkdrop can be reclaimed.
+    /// @allow ["::admins", "::owner"]
+    pub fn create_linkdrop(
+        &mut self,
+        public_key: PublicKey,
+        num: Option<u16>,
+        expires_at: Option<TimestampMs>,
+    ) -> Promise {
+        self.assert_owner_or_admin();
+        self.reserve_linkdrop(public_key, num.unwrap_or(1), expires_at)
+    }
+
+    /// Settle a linkdrop stuck `Claiming` because its claim's last callback never ran,
+    /// e.g. `on_claim` ran out of gas. If its tokens were handed out it becomes `Claimed`,
+    /// otherwise its key is added back so it can be claimed or revoked.
+    /// Returns whether it was claimed.
+    /// @allow ["::admins", "::owner"]
+    pub fn resolve_stale_linkdrop(&mut self, public_key: PublicKey) -> bool {
+        self.assert_owner_or_admin();
+        self.settle_stale_claim(public_key)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_contract;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn context(prepaid_gas: Gas) -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .prepaid_gas(prepaid_gas);
+        context
+    }
+
+    fn setup() -> Contract {
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64)).build());
+        new_contract(Sale::default())
+    }
+
+    fn balance_of(contract: &Contract, account_id: AccountId) -> u128 {
+        contract.nft_supply_for_owner(account_id).0
+    }
+
+    #[test]
+    #[should_panic(expected = "presale price must not be higher than the public price")]
+    fn new_validates_sale_schedule() {
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64)).build());
+        new_contract(Sale {
+            presale_price: Some(U128(1)),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "presale price must not be higher than the public price")]
+    fn price_below_presale_price() {
+        let mut contract = setup();
+        contract.update_presale_price(Some(U128(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "presale must not start after the public sale")]
+    fn presale_after_public_sale() {
+        let mut contract = setup();
+        contract.update_public_sale_start(100);
+        contract.update_presale_start(200);
+    }
+
+    #[test]
+    fn airdrop_to_all_recipients() {
+        let mut contract = setup();
+        let recipients = vec![(accounts(1), 2), (accounts(2), 0), (accounts(3), 3)];
+        assert_eq!(contract.airdrop_mint(recipients, None), None);
+        assert_eq!(balance_of(&contract, accounts(1)), 2);
+        assert_eq!(balance_of(&contract, accounts(3)), 3);
+        assert_eq!(contract.tokens_left(), 5);
+    }
+
+    #[test]
+    fn resume_airdrop_within_a_recipient() {
+        let mut contract = setup();
+        let recipients = vec![(accounts(1), 4), (accounts(2), 1)];
+        testing_env!(context(Gas(parse_gas!("28 Tgas") as u64)).build());
+        let cursor = contract.airdrop_mint(recipients.clone(), None);
+        assert_eq!(
+            cursor,
+            Some(AirdropCursor {
+                recipient: 0,
+                minted: 2
+            })
+        );
+        assert_eq!(balance_of(&contract, accounts(1)), 2);
+
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64)).build());
+        assert_eq!(contract.airdrop_mint(recipients, cursor), None);
+        assert_eq!(balance_of(&contract, accounts(1)), 4);
+        assert_eq!(balance_of(&contract, accounts(2)), 1);
+        assert_eq!(contract.tokens_left(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cursor is past the recipient's tokens")]
+    fn airdrop_cursor_past_recipient() {
+        let mut contract = setup();
+        let cursor = AirdropCursor {
+            recipient: 0,
+            minted: 3,
+        };
+        contract.airdrop_mint(vec![(accounts(1), 2)], Some(cursor));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough tokens left for airdrop")]
+    fn airdrop_more_than_supply() {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 6), (accounts(2), 5)], None);
+    }
+
+    fn backgrounds() -> Vec<TraitCategory> {
+        vec![TraitCategory {
+            name: "background".to_string(),
+            options: vec![TraitOption {
+                value: "blue".to_string(),
+                weight: 1,
+            }],
+        }]
+    }
+
+    fn extra_of(contract: &Contract, index: u64) -> Option<String> {
+        contract
+            .nft_token_by_mint_index(index)
+            .unwrap()
+            .metadata
+            .unwrap()
+            .extra
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be changed before the sale")]
+    fn set_traits_after_mint_when_none_were_set() {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        contract.update_traits(backgrounds());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be changed before the sale")]
+    fn traits_fixed_once_minting() {
+        let mut contract = setup();
+        contract.update_traits(backgrounds());
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        contract.update_traits(backgrounds());
+    }
+
+    #[test]
+    fn loaded_extra_skips_trait_draw() {
+        let mut contract = setup();
+        contract.update_traits(backgrounds());
+        let entries = (0..10)
+            .map(|index| {
+                let entry = TokenMetadataOverride {
+                    extra: Some(r#"{"background":"gold"}"#.to_string()),
+                    ..Default::default()
+                };
+                (index.to_string(), entry)
+            })
+            .collect();
+        contract.load_token_metadata(entries);
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        assert_eq!(
+            extra_of(&contract, 0).as_deref(),
+            Some(r#"{"background":"gold"}"#)
+        );
+        assert_eq!(contract.trait_distribution()[0].options[0].count, 0);
+    }
+
+    fn assert_supply(contract: &Contract, left: u32, final_supply: u64) {
+        assert_eq!(contract.tokens_left(), left);
+        assert_eq!(contract.initial(), final_supply);
+        assert_eq!(contract.get_sale_info().token_final_supply, final_supply);
+    }
+
+    #[test]
+    fn extend_and_reduce_supply() {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 2)], None);
+        assert_supply(&contract, 8, 10);
+        contract.extend_supply(5);
+        assert_supply(&contract, 13, 15);
+        contract.reduce_supply(3, SupplyReduction::Random);
+        assert_supply(&contract, 10, 12);
+        contract.airdrop_mint(vec![(accounts(1), 10)], None);
+        assert_supply(&contract, 0, 12);
+    }
+
+    #[test]
+    fn reduce_highest_before_minting() {
+        let mut contract = setup();
+        contract.reduce_supply(3, SupplyReduction::Highest);
+        contract.extend_supply(2);
+        contract.reduce_supply(1, SupplyReduction::Highest);
+        assert_supply(&contract, 8, 8);
+        contract.airdrop_mint(vec![(accounts(1), 8)], None);
+        let mut token_ids: Vec<u64> = (0..8)
+            .map(|index| {
+                let token = contract.nft_token_by_mint_index(index).unwrap();
+                token.token_id.parse().unwrap()
+            })
+            .collect();
+        token_ids.sort_unstable();
+        assert_eq!(token_ids, vec![0, 1, 2, 3, 4, 5, 6, 10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tokens were drawn randomly, so the highest left can't be removed")]
+    fn reduce_highest_after_random_draws() {
+        let mut contract = setup();
+        contract.airdrop_mint(vec![(accounts(1), 5)], None);
+        contract.reduce_supply(1, SupplyReduction::Highest);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't randomly remove more than 65535 tokens at once")]
+    fn reduce_random_supply_fits_draw_index() {
+        let mut contract = setup();
+        contract.extend_supply(u16::MAX as u32);
+        contract.reduce_supply(u16::MAX as u32 + 1, SupplyReduction::Random);
+    }
+
+    #[test]
+    fn burn_rest_after_sale() {
+        let mut contract = setup();
+        assert!(contract.can_reduce_highest_supply());
+        contract.sale.public_sale_start = Some(0);
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64))
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(parse_near!("1 N"))
+            .block_timestamp(1_000_000)
+            .build());
+        contract.nft_mint_many(3);
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64)).build());
+        assert!(!contract.can_reduce_highest_supply());
+        contract.reduce_supply(7, SupplyReduction::Random);
+        assert_supply(&contract, 0, 3);
+    }
+
+    fn load(contract: &mut Contract, token_id: &str, entry: TokenMetadataOverride) -> u32 {
+        contract.load_token_metadata(HashMap::from([(token_id.to_string(), entry)]))
+    }
+
+    fn titled(title: &str) -> TokenMetadataOverride {
+        TokenMetadataOverride {
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn loaded_metadata_takes_precedence() {
+        let mut contract = setup();
+        (0..10).for_each(|index| {
+            load(&mut contract, &index.to_string(), titled("loaded"));
+        });
+        assert_eq!(contract.token_metadata_progress().loaded, 10);
+        contract.airdrop_mint(vec![(accounts(1), 1)], None);
+        let token = contract.nft_token_by_mint_index(0).unwrap();
+        assert_eq!(token.metadata.unwrap().title.as_deref(), Some("loaded"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Method is private to owner")]
+    fn admins_cannot_load_metadata() {
+        let mut contract = setup();
+        contract.add_admin(accounts(1));
+        testing_env!(context(Gas(parse_gas!("300 Tgas") as u64))
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
+            .build());
+        load(&mut contract, "0", titled("loaded"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token 10 is not in the pool")]
+    fn load_outside_pool() {
+        let mut contract = setup();
+        load(&mut contract, "10", titled("loaded"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token 9 is not in the pool")]
+    fn load_removed_token() {
+        let mut contract = setup();
+        contract.reduce_supply(1, SupplyReduction::Highest);
+        load(&mut contract, "9", titled("loaded"));
+    }
+
+    #[test]
+    fn removing_tokens_drops_their_metadata() {
+        let mut contract = setup();
+        load(&mut contract, "9", titled("loaded"));
+        load(&mut contract, "0", titled("loaded"));
+        contract.reduce_supply(1, SupplyReduction::Highest);
+        let progress = contract.token_metadata_progress();
+        assert_eq!((progress.loaded, progress.total), (1, 9));
+    }
+
+    #[test]
+    #[should_panic(expected = "Hash has to be 32 bytes")]
+    fn loaded_hash_must_be_32_bytes() {
+        let mut contract = setup();
+        let entry = TokenMetadataOverride {
+            media_hash: Some(vec![0; 31].into()),
+            ..Default::default()
+        };
+        load(&mut contract, "0", entry);
+    }
+
+    fn patch(json: &str) -> ContractMetadataPatch {
+        near_sdk::serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn update_part_of_contract_metadata() {
+        let mut contract = setup();
+        let metadata = contract.update_contract_metadata(patch(r#"{"name": "new"}"#), None);
+        assert_eq!(metadata.name, "new");
+        assert_eq!(metadata.symbol, "sym");
+        assert_eq!(metadata.base_uri.as_deref(), Some("https://"));
+        assert_eq!(contract.nft_metadata().name, "new");
+
+        let metadata = contract.update_contract_metadata(patch(r#"{"base_uri": null}"#), None);
+        assert_eq!(metadata.name, "new");
+        assert_eq!(metadata.base_uri, None);
+    }
+
+    #[test]
+    fn contract_metadata_update_is_logged() {
+        let mut contract = setup();
+        contract.update_contract_metadata(patch(r#"{"symbol": "new"}"#), None);
+        let logs = get_logs();
+        let update_log = logs.last().unwrap();
+        assert!(update_log.starts_with("EVENT_JSON:"));
+        assert!(update_log.contains(r#""event":"contract_metadata_update""#));
+    }
+
+    #[test]
+    #[should_panic(expected = "Spec is not NFT metadata")]
+    fn invalid_contract_metadata() {
+        let mut contract = setup();
+        contract.update_contract_metadata(patch(r#"{"spec": "nft-0.0.0"}"#), None);
+    }
+
+    fn frozen() -> Contract {
+        let mut contract = setup();
+        contract.update_contract_metadata(ContractMetadataPatch::default(), Some(true));
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract metadata is frozen")]
+    fn frozen_contract_metadata() {
+        frozen().update_contract_metadata(patch(r#"{"name": "new"}"#), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract metadata is frozen")]
+    fn uri_frozen_with_metadata() {
+        frozen().update_uri("ipfs://".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract metadata is frozen")]
+    fn base_uri_ranges_frozen_with_metadata() {
+        frozen().add_base_uri_range(0, 5, "ipfs://".to_string());
     }
 }
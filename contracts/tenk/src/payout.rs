@@ -62,9 +62,7 @@ impl Payouts for Contract {
             .owner_by_id
             .get(&token_id)
             .expect("No such token_id");
-        self.sale
-            .royalties
-            .as_ref()
+        self.royalties_of(&token_id)
             .map_or(Payout::default(), |r| r.create_payout(balance.0, &owner_id))
     }
 
@@ -91,7 +89,7 @@ type BasisPoint = u16;
 const ONE_HUNDRED_PERCENT_IN_BPS: BasisPoint = 10_000;
 
 #[near_sdk::witgen]
-#[derive(BorshSerialize, BorshDeserialize, Deserialize, Serialize, Default)]
+#[derive(BorshSerialize, BorshDeserialize, Deserialize, Serialize, Default, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Royalties {
     pub accounts: HashMap<AccountId, BasisPoint>,
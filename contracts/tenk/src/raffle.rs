@@ -22,6 +22,12 @@ pub struct Raffle {
     prefix: Vec<u8>,
     #[borsh_skip]
     el: PhantomData<u64>,
+    /// Number of values ever added, drawn or not. The next value added is `total`.
+    total: u64,
+    /// Number of values removed from the front with `shift`. Indexes are relative to it.
+    head: u64,
+    /// Set once a draw moved a value out of ascending order
+    shuffled: bool,
 }
 
 impl Raffle {
@@ -37,6 +43,14 @@ impl Raffle {
 
     /// Create new vector with zero elements. Use `id` as a unique identifier on the trie.
     pub fn new<S>(prefix: S, len: u64) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::restore(prefix, len, len)
+    }
+
+    /// Recreate a raffle from its parts, e.g. when migrating from a layout without `total`
+    pub(crate) fn restore<S>(prefix: S, len: u64, total: u64) -> Self
     where
         S: IntoStorageKey,
     {
@@ -44,11 +58,20 @@ impl Raffle {
             len,
             prefix: prefix.into_storage_key(),
             el: PhantomData,
+            total,
+            head: 0,
+            // Values were only ever drawn randomly before `total` was tracked
+            shuffled: len < total,
         }
     }
 
+    /// Number of values ever added, including those already drawn or removed
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
     fn index_to_lookup_key(&self, index: u64) -> Vec<u8> {
-        append_slice(&self.prefix, &index.to_le_bytes()[..])
+        append_slice(&self.prefix, &(self.head + index).to_le_bytes()[..])
     }
 
     /// # Panics
@@ -60,6 +83,7 @@ impl Raffle {
         } else if index + 1 == self.len {
             expect_consistent_state(self.pop_raw())
         } else {
+            self.shuffled = true;
             let lookup_key = self.index_to_lookup_key(index);
             let raw_last_value = self
                 .pop_raw()
@@ -68,7 +92,7 @@ impl Raffle {
                 expect_consistent_state(env::storage_get_evicted())
             } else {
                 // no value was at location its index is the value
-                index.to_le_bytes().to_vec()
+                (self.head + index).to_le_bytes().to_vec()
             }
         }
     }
@@ -85,15 +109,58 @@ impl Raffle {
                 expect_consistent_state(env::storage_get_evicted())
             } else {
                 // otherwise the value is index of the last element
-                self.len.to_le_bytes().to_vec()
+                (self.head + self.len).to_le_bytes().to_vec()
             };
             Some(raw_last_value)
         }
     }
 
-    pub fn draw(&mut self) -> u64 {
-        let seed_num = crate::util::get_random_number(0) as u64;
-        u64::try_from_slice(&self.swap_remove_raw(seed_num % self.len())).unwrap()
+    /// Add `num` new values to the end of the raffle, continuing after the last value ever added.
+    pub fn extend(&mut self, num: u64) {
+        for i in 0..num {
+            let index = self.len + i;
+            let value = self.total + i;
+            // An index without a stored value holds the index itself
+            if self.head + index != value {
+                env::storage_write(&self.index_to_lookup_key(index), &value.to_le_bytes());
+            }
+        }
+        self.len += num;
+        self.total += num;
+    }
+
+    /// Remove and return the element at the front of the raffle.
+    /// As long as values were only removed this way they come out in ascending order.
+    pub fn shift(&mut self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = if env::storage_remove(&self.index_to_lookup_key(0)) {
+            u64::try_from_slice(&expect_consistent_state(env::storage_get_evicted())).unwrap()
+        } else {
+            self.head
+        };
+        self.head += 1;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// `true` when no draw has moved a value, so the rest are still in ascending order:
+    /// `shift` returns the lowest value left and `pop` the highest
+    pub fn is_ordered(&self) -> bool {
+        !self.shuffled
+    }
+
+    /// Remove and return the element at the end of the raffle.
+    /// This is the highest value left as long as the raffle `is_ordered`.
+    pub fn pop(&mut self) -> Option<u64> {
+        self.pop_raw().map(|raw| u64::try_from_slice(&raw).unwrap())
+    }
+
+    /// Remove and return a random element, chosen by `random`.
+    /// See `util::random_u64` for deriving independent values for several draws in one call.
+    pub fn draw(&mut self, random: u64) -> u64 {
+        u64::try_from_slice(&self.swap_remove_raw(random % self.len())).unwrap()
     }
 }
 
@@ -104,7 +171,10 @@ mod tests {
     use std::collections::HashSet;
 
     use super::Raffle;
-    use near_sdk::test_utils::VMContextBuilder;
+    use crate::test_utils::new_contract;
+    use crate::util::random_u64;
+    use crate::{Contract, Sale};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::testing_env;
 
     #[test]
@@ -116,9 +186,77 @@ mod tests {
         testing_env!(context.build());
         for _ in 0..100 {
             let len = vec.len();
-            assert!(set.insert(vec.draw()));
+            assert!(set.insert(vec.draw(rng.gen())));
             assert_eq!(len - 1, vec.len());
             testing_env!(context.random_seed(rng.gen()).build());
         }
     }
+
+    #[test]
+    pub fn test_extend_and_pop() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut raffle = Raffle::new(b"x".to_vec(), 10);
+        let mut set: HashSet<u64> = HashSet::new();
+        for i in 0..5 {
+            assert!(set.insert(raffle.draw(random_u64(i, &accounts(0), i))));
+        }
+        raffle.extend(5);
+        assert_eq!(raffle.len(), 10);
+        assert_eq!(raffle.total(), 15);
+        assert_eq!(raffle.pop(), Some(14));
+        assert!(set.insert(14));
+        for i in 0..9 {
+            assert!(set.insert(raffle.draw(random_u64(i + 5, &accounts(0), i))));
+        }
+        assert!(raffle.is_empty());
+        assert_eq!(set, (0..15).collect());
+    }
+
+    #[test]
+    pub fn test_shift_in_order() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut raffle = Raffle::new(b"s".to_vec(), 3);
+        assert_eq!(raffle.shift(), Some(0));
+        raffle.extend(2);
+        assert!(raffle.is_ordered());
+        let values: Vec<u64> = std::iter::from_fn(|| raffle.shift()).collect();
+        assert_eq!(values, vec![1, 2, 3, 4]);
+        assert_eq!(raffle.total(), 5);
+
+        let mut raffle = Raffle::new(b"t".to_vec(), 3);
+        assert_eq!(raffle.pop(), Some(2));
+        raffle.extend(1);
+        assert!(raffle.is_ordered());
+        assert_eq!(raffle.pop(), Some(3));
+        raffle.draw(0);
+        assert!(!raffle.is_ordered());
+    }
+
+    #[test]
+    pub fn test_draws_in_one_call_are_independent() {
+        testing_env!(VMContextBuilder::new().random_seed([7; 32]).build());
+        let mut contract = new_contract(Sale::default());
+        let minter = accounts(1);
+        let draw_many = |contract: &mut Contract| {
+            let nonce = contract.draw_nonce;
+            let randoms: Vec<u64> = (0..10).map(|i| contract.next_random(&minter, i)).collect();
+            assert_eq!(contract.draw_nonce, nonce + 10);
+            randoms
+        };
+
+        // Every draw by the same minter in the same block picks its own index,
+        // where reusing the block seed would keep picking the same one
+        let randoms = draw_many(&mut contract);
+        let len = 1_000;
+        let indexes: HashSet<u64> = randoms
+            .iter()
+            .enumerate()
+            .map(|(i, random)| random % (len - i as u64))
+            .collect();
+        assert_eq!(indexes.len(), 10);
+
+        // A later call in the same block continues from the advanced nonce
+        let later: HashSet<u64> = draw_many(&mut contract).into_iter().collect();
+        assert!(later.is_disjoint(&randoms.into_iter().collect()));
+    }
 }
@@ -66,29 +66,28 @@ impl RaffleCollection {
     }
 
     fn pop(&mut self) -> u32 {
-        let last_index = self.len();
         self.len -= 1;
+        let last_index = self.len();
         self.inner_map.remove(&last_index).unwrap_or(last_index)
     }
 
-    pub fn draw_raw(&mut self) -> u32 {
-        let seed_num = crate::util::get_random_number(0);
-        self.swap_remove(seed_num % self.len())
+    pub fn draw_raw(&mut self, random: u64) -> u32 {
+        self.swap_remove((random % self.len() as u64) as u32)
     }
 
-    pub fn draw(&mut self) -> Option<u32> {
+    /// Draw a new winner using `random`, or `None` if there are already `max_winners`.
+    pub fn draw(&mut self, random: u64) -> Option<u32> {
         require!(!self.is_empty(), "Nothing left to draw");
         if self.num_winners() == self.max_winners {
             return None;
         }
-        let res = self.draw_raw();
+        let res = self.draw_raw(random);
         Some(res)
     }
 
     pub fn get_winners(&self, index: Option<u32>, limit: Option<u32>) -> Vec<u32> {
         let limit = limit.unwrap_or_else(|| self.num_winners()) as usize;
         let starting_index = index.unwrap_or(0) as usize;
-        env::log_str(&format!("{}, len {}", self.num_winners(), self.len()));
         self.winners
             .iter()
             .skip(starting_index)
@@ -99,6 +98,11 @@ impl RaffleCollection {
     pub fn num_winners(&self) -> u32 {
         self.winners.len() as u32
     }
+
+    /// Returns `true` once there are `max_winners` or nothing is left to draw.
+    pub fn is_done(&self) -> bool {
+        self.is_empty() || self.num_winners() == self.max_winners
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -122,24 +126,24 @@ mod tests {
         testing_env!(context.build());
         for i in 0..50 {
             let len = vec.len();
-            let val = vec.draw().unwrap();
+            let val = vec.draw(rng.gen()).unwrap();
+            assert!(val < 100);
             assert!(set.insert(val));
             let winner = vec.get_winners(Some(i), Some(1));
             assert_eq!(winner, vec![val]);
-            let next = rng.gen::<u64>().to_le_bytes().to_vec();
-            testing_env!(context.random_seed(next).build());
+            testing_env!(context.random_seed(rng.gen()).build());
             assert_eq!(len - 1, vec.len())
         }
 
         for _ in 0..49 {
             let len = vec.len();
-            let val = vec.draw().unwrap();
+            let val = vec.draw(rng.gen()).unwrap();
+            assert!(val < 100);
             assert!(set.insert(val));
-            let next = rng.gen::<u64>().to_le_bytes().to_vec();
-            testing_env!(context.random_seed(next).build());
+            testing_env!(context.random_seed(rng.gen()).build());
             assert_eq!(len - 1, vec.len())
         }
-        let val = vec.draw();
+        let val = vec.draw(rng.gen());
         assert!(val.is_none());
         assert_eq!(vec.num_winners(), 99);
     }
@@ -0,0 +1,581 @@
+use crate::*;
+
+/// Name of a series, used as the namespace of its token ids, e.g. `genesis:42`
+#[witgen]
+pub type SeriesId = String;
+
+pub(crate) const SERIES_SEPARATOR: char = ':';
+
+/// A drop with its own pool of tokens, sale and metadata, minted from the same contract
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Series {
+    name: String,
+    raffle: Raffle,
+    minted: u64,
+    sale: Sale,
+    metadata_template: MetadataTemplate,
+    base_uri: Option<String>,
+    media_extension: Option<String>,
+}
+
+/// Configuration of a series and how much of it is left
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesInfo {
+    pub series_id: SeriesId,
+    pub name: String,
+    pub base_uri: Option<String>,
+    pub media_extension: Option<String>,
+    pub metadata_template: MetadataTemplate,
+    pub sale: Sale,
+    /// Tokens left to be minted
+    pub tokens_left: u64,
+    /// Total tokens that could be minted
+    pub token_final_supply: u64,
+}
+
+impl Series {
+    fn tokens_left(&self) -> u32 {
+        self.raffle.len() as u32
+    }
+
+    fn status(&self) -> Status {
+        self.sale.status(self.tokens_left())
+    }
+
+    fn price(&self) -> u128 {
+        self.sale.current_price(&self.status())
+    }
+
+    fn initial(&self) -> u64 {
+        self.raffle.len() + self.minted
+    }
+
+    fn info(&self, series_id: SeriesId) -> SeriesInfo {
+        SeriesInfo {
+            series_id,
+            name: self.name.clone(),
+            base_uri: self.base_uri.clone(),
+            media_extension: self.media_extension.clone(),
+            metadata_template: self.metadata_template.clone(),
+            sale: self.sale.clone(),
+            tokens_left: self.raffle.len(),
+            token_final_supply: self.initial(),
+        }
+    }
+
+    fn sale_info(&self) -> SaleInfo {
+        SaleInfo {
+            presale_start: self.sale.presale_start.unwrap_or(MAX_DATE),
+            sale_start: self.sale.public_sale_start.unwrap_or(MAX_DATE),
+            status: self.status(),
+            price: self.price().into(),
+            token_final_supply: self.initial(),
+        }
+    }
+
+    fn create_metadata(&self, id: &str) -> TokenMetadata {
+        let ext = self.media_extension.as_deref().unwrap_or("png");
+        let mut metadata = self.metadata_template.render(id, &self.name, ext);
+        if let Some(base_uri) = self.base_uri.as_deref() {
+            set_base_uri(&mut metadata, base_uri);
+        }
+        metadata
+    }
+}
+
+/// Series a token id belongs to, if it is namespaced as `series:id`
+pub(crate) fn series_of(token_id: &str) -> Option<&str> {
+    token_id
+        .split_once(SERIES_SEPARATOR)
+        .map(|(series_id, _)| series_id)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Create a new series with its own pool of `size` tokens
+    /// @allow ["::admins", "::owner"]
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_series(
+        &mut self,
+        series_id: SeriesId,
+        name: String,
+        size: u32,
+        sale: Sale,
+        base_uri: Option<String>,
+        media_extension: Option<String>,
+        metadata_template: Option<MetadataTemplate>,
+    ) -> SeriesInfo {
+        self.assert_owner_or_admin();
+        require!(
+            !series_id.is_empty() && !series_id.contains(SERIES_SEPARATOR),
+            "series id must be non-empty and can't contain ':'"
+        );
+        require!(
+            self.series.get(&series_id).is_none(),
+            "Series already exists"
+        );
+        sale.validate();
+        sale.validate_schedule();
+        if let Some(ext) = media_extension.as_ref() {
+            require!(
+                !ext.starts_with('.'),
+                "media extension must not start with '.'"
+            );
+        }
+        let series = Series {
+            name,
+            raffle: Raffle::new(
+                StorageKey::SeriesRaffle {
+                    series_id: series_id.clone(),
+                },
+                size as u64,
+            ),
+            minted: 0,
+            sale,
+            metadata_template: metadata_template.unwrap_or_default(),
+            base_uri,
+            media_extension,
+        };
+        self.series.insert(&series_id, &series);
+        series.info(series_id)
+    }
+
+    /// Update any part of a series' sale in one call, like `update_sale`
+    /// @allow ["::admins", "::owner"]
+    pub fn update_series_sale(&mut self, series_id: SeriesId, patch: SalePatch) -> Sale {
+        self.assert_owner_or_admin();
+        let mut series = self.get_series_or_panic(&series_id);
+        series.sale.apply(patch);
+        series.sale.validate();
+        series.sale.validate_schedule();
+        self.series.insert(&series_id, &series);
+        series.sale
+    }
+
+    /// @allow ["::admins", "::owner"]
+    pub fn update_series_base_uri(
+        &mut self,
+        series_id: SeriesId,
+        base_uri: Option<String>,
+    ) -> bool {
+        self.assert_owner_or_admin();
+        let mut series = self.get_series_or_panic(&series_id);
+        series.base_uri = base_uri;
+        self.series.insert(&series_id, &series);
+        true
+    }
+
+    /// Add accounts to the presale of a series at a given max allowance
+    /// @allow ["::admins", "::owner"]
+    pub fn add_series_whitelist_accounts(
+        &mut self,
+        series_id: SeriesId,
+        accounts: Vec<AccountId>,
+        max_allowance: Option<u16>,
+    ) -> bool {
+        self.assert_owner_or_admin();
+        let series = self.get_series_or_panic(&series_id);
+        let max_allowance = max_allowance.unwrap_or_else(|| series.sale.allowance.unwrap_or(0));
+        accounts.into_iter().for_each(|account_id| {
+            let key = (series_id.clone(), account_id);
+            let allowance = self
+                .series_whitelist
+                .get(&key)
+                .unwrap_or_else(|| Allowance::new(max_allowance))
+                .raise_max(max_allowance);
+            self.series_whitelist.insert(&key, &allowance);
+        });
+        true
+    }
+
+    /// Mint `num` random tokens from a series. The owner skips the price and allowance
+    /// but still attaches enough to cover storage.
+    #[payable]
+    pub fn nft_mint_series(&mut self, series_id: SeriesId, num: u16) -> Vec<Token> {
+        let owner_id = &env::signer_account_id();
+        let mut series = self.get_series_or_panic(&series_id);
+        if let Some(limit) = series.sale.mint_rate_limit {
+            require!(num <= limit, "over mint limit");
+        }
+        let num = self.assert_can_mint_series(&series_id, &series, owner_id, num);
+        let is_owner = self.is_owner(owner_id);
+        let has_allowance =
+            series.sale.allowance.is_some() || matches!(series.status(), Status::Presale);
+        let initial_storage_usage = env::storage_usage();
+
+        let tokens: Vec<Token> = (0..num)
+            .map(|i| {
+                let random = self.next_random(owner_id, i);
+                let id = self.token_id_format.format(series.raffle.draw(random));
+                series.minted += 1;
+                self.series_minted += 1;
+                let token_id = format!("{}{}{}", series_id, SERIES_SEPARATOR, id);
+                let metadata = series.create_metadata(&id);
+                self.internal_mint_with_metadata(token_id, metadata, owner_id.clone(), None)
+            })
+            .collect();
+        self.series.insert(&series_id, &series);
+
+        let storage_used = env::storage_usage() - initial_storage_usage;
+        let initial_royalties = series.sale.initial_royalties.as_ref().filter(|_| !is_owner);
+        self.pay_for_mint(storage_used, initial_royalties);
+        if !is_owner && has_allowance {
+            self.use_series_allowance(&series_id, owner_id, num);
+        }
+        log_mint(owner_id, &tokens);
+        tokens
+    }
+
+    /// Configuration of a series
+    pub fn get_series(&self, series_id: SeriesId) -> Option<SeriesInfo> {
+        self.series
+            .get(&series_id)
+            .map(|series| series.info(series_id))
+    }
+
+    /// Paginated list of all series
+    pub fn list_series(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<SeriesInfo> {
+        let start = from_index.map_or(0, |index| index.0 as usize);
+        self.series
+            .iter()
+            .skip(start)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|(series_id, series)| series.info(series_id))
+            .collect()
+    }
+
+    /// Information about the current sale of a series
+    pub fn get_series_sale_info(&self, series_id: SeriesId) -> SaleInfo {
+        self.get_series_or_panic(&series_id).sale_info()
+    }
+
+    /// Cost of minting `num` tokens from a series
+    pub fn series_total_cost(&self, series_id: SeriesId, num: u16, minter: &AccountId) -> U128 {
+        if self.is_owner(minter) {
+            0
+        } else {
+            num as Balance * self.get_series_or_panic(&series_id).price()
+        }
+        .into()
+    }
+}
+
+impl Contract {
+    fn get_series_or_panic(&self, series_id: &str) -> Series {
+        self.series
+            .get(&series_id.to_string())
+            .unwrap_or_else(|| env::panic_str("Series doesn't exist"))
+    }
+
+    /// Royalties of the series `token_id` belongs to, or of the contract if it isn't part of one
+    pub(crate) fn royalties_of(&self, token_id: &str) -> Option<Royalties> {
+        series_of(token_id)
+            .and_then(|series_id| self.series.get(&series_id.to_string()))
+            .map_or_else(
+                || self.sale.royalties.clone(),
+                |series| series.sale.royalties,
+            )
+    }
+
+    fn assert_can_mint_series(
+        &mut self,
+        series_id: &str,
+        series: &Series,
+        account_id: &AccountId,
+        num: u16,
+    ) -> u16 {
+        let mut num = num;
+        if !self.is_owner(account_id) {
+            let key = (series_id.to_string(), account_id.clone());
+            let allowance = match series.status() {
+                Status::SoldOut => env::panic_str("No NFTs left to mint"),
+                Status::Closed => env::panic_str("Series currently closed"),
+                Status::Presale => self
+                    .series_whitelist
+                    .get(&key)
+                    .unwrap_or_else(|| env::panic_str("Account not on whitelist"))
+                    .left(),
+                Status::Open => series.sale.allowance.map_or(num, |public_allowance| {
+                    let allowance = self
+                        .series_whitelist
+                        .get(&key)
+                        .unwrap_or_else(|| Allowance::new(public_allowance))
+                        .raise_max(public_allowance);
+                    self.series_whitelist.insert(&key, &allowance);
+                    allowance.left()
+                }),
+            };
+            num = u16::min(allowance, num);
+            require!(num > 0, "Account has no more allowance left");
+        }
+        require!(series.tokens_left() >= num as u32, "No NFTs left to mint");
+        let cost = self.series_total_cost(series_id.to_string(), num, account_id);
+        require!(
+            env::attached_deposit() >= cost.0,
+            "Not enough attached deposit to buy"
+        );
+        num
+    }
+
+    fn use_series_allowance(&mut self, series_id: &str, account_id: &AccountId, num: u16) {
+        let key = (series_id.to_string(), account_id.clone());
+        if let Some(mut allowance) = self.series_whitelist.get(&key) {
+            allowance.use_num(num);
+            self.series_whitelist.insert(&key, &allowance);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_contract;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    const MS: u64 = 1_000_000;
+    const ONE_NEAR: Balance = parse_near!("1 N");
+
+    fn context(predecessor: AccountId, now_ms: u64) -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .signer_account_id(predecessor.clone())
+            .predecessor_account_id(predecessor)
+            .block_timestamp(now_ms * MS)
+            .attached_deposit(ONE_NEAR);
+        context
+    }
+
+    fn setup() -> Contract {
+        testing_env!(context(accounts(0), 0).build());
+        new_contract(Sale::default())
+    }
+
+    fn public_sale() -> Sale {
+        Sale {
+            price: ONE_NEAR.into(),
+            public_sale_start: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn create(contract: &mut Contract, series_id: &str, sale: Sale) -> SeriesInfo {
+        testing_env!(context(accounts(0), 0).build());
+        contract.create_series(
+            series_id.to_string(),
+            "Genesis".to_string(),
+            5,
+            sale,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn mint(contract: &mut Contract, account_id: AccountId, num: u16) -> Vec<Token> {
+        testing_env!(context(account_id, 10).build());
+        contract.nft_mint_series("s".to_string(), num)
+    }
+
+    fn royalties(account_id: AccountId) -> Royalties {
+        Royalties {
+            accounts: HashMap::from([(account_id, 10_000)]),
+            percent: 500,
+        }
+    }
+
+    #[test]
+    fn create_series() {
+        let mut contract = setup();
+        let info = create(&mut contract, "s", public_sale());
+        assert_eq!(info.series_id, "s");
+        assert_eq!(info.tokens_left, 5);
+        assert_eq!(info.token_final_supply, 5);
+        assert_eq!(
+            contract.get_series("s".to_string()).unwrap().name,
+            "Genesis"
+        );
+        assert!(contract.get_series("t".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Series already exists")]
+    fn create_series_twice() {
+        let mut contract = setup();
+        create(&mut contract, "s", public_sale());
+        create(&mut contract, "s", public_sale());
+    }
+
+    #[test]
+    #[should_panic(expected = "series id must be non-empty and can't contain ':'")]
+    fn series_id_without_separator() {
+        let mut contract = setup();
+        create(&mut contract, "s:1", public_sale());
+    }
+
+    #[test]
+    #[should_panic(expected = "Method is private to owner or admin")]
+    fn only_admins_create_series() {
+        let mut contract = setup();
+        testing_env!(context(accounts(1), 0).build());
+        contract.create_series(
+            "s".to_string(),
+            "Genesis".to_string(),
+            5,
+            public_sale(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn mint_from_series() {
+        let mut contract = setup();
+        create(&mut contract, "s", public_sale());
+        let tokens = mint(&mut contract, accounts(1), 1);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(series_of(&tokens[0].token_id), Some("s"));
+        assert_eq!(tokens[0].owner_id, accounts(1));
+        let info = contract.get_series("s".to_string()).unwrap();
+        assert_eq!((info.tokens_left, info.token_final_supply), (4, 5));
+        assert_eq!(contract.tokens_left(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account has no more allowance left")]
+    fn series_allowance() {
+        let mut contract = setup();
+        create(
+            &mut contract,
+            "s",
+            Sale {
+                allowance: Some(1),
+                ..public_sale()
+            },
+        );
+        mint(&mut contract, accounts(1), 1);
+        mint(&mut contract, accounts(1), 1);
+    }
+
+    fn presale() -> Sale {
+        Sale {
+            presale_start: Some(0),
+            presale_price: Some(ONE_NEAR.into()),
+            price: (2 * ONE_NEAR).into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn series_presale() {
+        let mut contract = setup();
+        create(&mut contract, "s", presale());
+        contract.add_series_whitelist_accounts("s".to_string(), vec![accounts(1)], Some(1));
+        assert_eq!(mint(&mut contract, accounts(1), 2).len(), 1);
+        let sale_info = contract.get_series_sale_info("s".to_string());
+        assert!(matches!(sale_info.status, Status::Presale));
+        assert_eq!(sale_info.price.0, ONE_NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account not on whitelist")]
+    fn series_presale_needs_whitelist() {
+        let mut contract = setup();
+        create(&mut contract, "s", presale());
+        mint(&mut contract, accounts(1), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough attached deposit to buy")]
+    fn series_payment() {
+        let mut contract = setup();
+        create(
+            &mut contract,
+            "s",
+            Sale {
+                price: (2 * ONE_NEAR).into(),
+                ..public_sale()
+            },
+        );
+        mint(&mut contract, accounts(1), 1);
+    }
+
+    #[test]
+    fn owner_mints_series_without_paying_price() {
+        let mut contract = setup();
+        create(
+            &mut contract,
+            "s",
+            Sale {
+                price: (2 * ONE_NEAR).into(),
+                ..public_sale()
+            },
+        );
+        assert_eq!(mint(&mut contract, accounts(0), 2).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn owner_pays_series_storage() {
+        let mut contract = setup();
+        create(&mut contract, "s", public_sale());
+        testing_env!(context(accounts(0), 10).attached_deposit(0).build());
+        contract.nft_mint_series("s".to_string(), 1);
+    }
+
+    #[test]
+    fn royalties_of_series() {
+        let mut contract = setup();
+        contract.sale.royalties = Some(royalties(accounts(2)));
+        create(
+            &mut contract,
+            "s",
+            Sale {
+                royalties: Some(royalties(accounts(3))),
+                ..public_sale()
+            },
+        );
+        let series_royalties = contract.royalties_of("s:1").unwrap();
+        assert!(series_royalties.accounts.contains_key(&accounts(3)));
+        let royalties = contract.royalties_of("1").unwrap();
+        assert!(royalties.accounts.contains_key(&accounts(2)));
+        let unknown = contract.royalties_of("t:1").unwrap();
+        assert!(unknown.accounts.contains_key(&accounts(2)));
+    }
+
+    #[test]
+    fn list_series() {
+        let mut contract = setup();
+        ["a", "b", "c"].iter().for_each(|series_id| {
+            create(&mut contract, series_id, public_sale());
+        });
+        let ids = |series: Vec<SeriesInfo>| -> Vec<SeriesId> {
+            series.into_iter().map(|info| info.series_id).collect()
+        };
+        assert_eq!(ids(contract.list_series(None, None)), vec!["a", "b", "c"]);
+        assert_eq!(ids(contract.list_series(Some(U128(1)), Some(1))), vec!["b"]);
+    }
+
+    #[test]
+    fn series_sale_info() {
+        let mut contract = setup();
+        create(&mut contract, "s", public_sale());
+        mint(&mut contract, accounts(1), 1);
+        let sale_info = contract.get_series_sale_info("s".to_string());
+        assert!(matches!(sale_info.status, Status::Open));
+        assert_eq!(sale_info.price.0, ONE_NEAR);
+        assert_eq!(sale_info.sale_start, 0);
+        assert_eq!(sale_info.token_final_supply, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Series doesn't exist")]
+    fn sale_info_of_unknown_series() {
+        let contract = setup();
+        contract.get_series_sale_info("s".to_string());
+    }
+}
@@ -0,0 +1,20 @@
+use crate::*;
+use near_sdk::test_utils::accounts;
+
+/// Metadata of the collection created by `new_contract`
+pub fn contract_metadata() -> NFTContractMetadata {
+    NFTContractMetadata {
+        spec: NFT_METADATA_SPEC.to_string(),
+        name: "name".to_string(),
+        symbol: "sym".to_string(),
+        icon: None,
+        base_uri: Some("https://".to_string()),
+        reference: None,
+        reference_hash: None,
+    }
+}
+
+/// Collection of 10 tokens owned by `accounts(0)`
+pub fn new_contract(sale: Sale) -> Contract {
+    Contract::new(accounts(0), contract_metadata(), 10, sale, None, None, None)
+}
@@ -0,0 +1,219 @@
+use crate::*;
+
+/// One possible value of a trait and how likely it is to be drawn relative to the others
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TraitOption {
+    pub value: String,
+    pub weight: u32,
+}
+
+/// A trait every token gets one option of, e.g. "background"
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TraitCategory {
+    pub name: String,
+    pub options: Vec<TraitOption>,
+}
+
+/// Number of minted tokens that got a given option
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TraitOptionCount {
+    pub value: String,
+    pub weight: u32,
+    pub count: u32,
+}
+
+/// Number of minted tokens per option of a trait
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TraitDistribution {
+    pub name: String,
+    pub options: Vec<TraitOptionCount>,
+}
+
+/// Traits drawn for each token and how often each option has been drawn so far
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct TraitTable {
+    categories: Vec<TraitCategory>,
+    counts: Vec<Vec<u32>>,
+    /// Write the drawn option on its own instead of a JSON object, as tokens minted
+    /// before traits could be configured had only their animal type in `extra`
+    plain: bool,
+}
+
+impl TraitTable {
+    pub fn new(categories: Vec<TraitCategory>) -> Self {
+        categories.iter().for_each(|category| {
+            require!(
+                !category.options.is_empty(),
+                format!("Trait {} has no options", category.name)
+            );
+            require!(
+                category.options.iter().all(|option| option.weight > 0),
+                format!(
+                    "Options of trait {} must have a positive weight",
+                    category.name
+                )
+            );
+            require!(
+                category
+                    .options
+                    .iter()
+                    .map(|option| option.weight as u64)
+                    .sum::<u64>()
+                    <= u32::MAX as u64,
+                format!("Total weight of trait {} is too large", category.name)
+            );
+        });
+        let counts = categories
+            .iter()
+            .map(|category| vec![0; category.options.len()])
+            .collect();
+        Self {
+            categories,
+            counts,
+            plain: false,
+        }
+    }
+
+    /// The NEARGotchi animal types, each equally likely, written to `extra` as just the type
+    pub fn animal_types() -> Self {
+        Self {
+            plain: true,
+            ..Self::new(vec![TraitCategory {
+                name: "animal_type".to_string(),
+                options: (1..=3)
+                    .map(|animal| TraitOption {
+                        value: animal.to_string(),
+                        weight: 1,
+                    })
+                    .collect(),
+            }])
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.categories.is_empty()
+    }
+
+    pub fn categories(&self) -> Vec<TraitCategory> {
+        self.categories.clone()
+    }
+
+    /// Draw an option of every trait for `token_id` and return them as a JSON object,
+    /// or just the option of the first trait if the table is `plain`.
+    /// The block's random seed is hashed together with the token id and the trait's index
+    /// so that tokens minted in the same block get independent traits.
+    pub fn draw(&mut self, token_id: &str) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let seed = env::random_seed();
+        let mut traits = near_sdk::serde_json::Map::new();
+        for (index, category) in self.categories.iter().enumerate() {
+            let hash = env::sha256(
+                &[
+                    &seed[..],
+                    token_id.as_bytes(),
+                    &(index as u32).to_le_bytes(),
+                ]
+                .concat(),
+            );
+            let mut arr: [u8; 4] = Default::default();
+            arr.copy_from_slice(&hash[..4]);
+            let choice = pick_weighted(&category.options, u32::from_le_bytes(arr));
+            self.counts[index][choice] += 1;
+            if self.plain {
+                return Some(category.options[choice].value.clone());
+            }
+            traits.insert(
+                category.name.clone(),
+                category.options[choice].value.clone().into(),
+            );
+        }
+        Some(near_sdk::serde_json::Value::Object(traits).to_string())
+    }
+
+    pub fn distribution(&self) -> Vec<TraitDistribution> {
+        self.categories
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(category, counts)| TraitDistribution {
+                name: category.name.clone(),
+                options: category
+                    .options
+                    .iter()
+                    .zip(counts.iter())
+                    .map(|(option, count)| TraitOptionCount {
+                        value: option.value.clone(),
+                        weight: option.weight,
+                        count: *count,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Index of the option that `random` lands on when options take up space proportional to their weight
+fn pick_weighted(options: &[TraitOption], random: u32) -> usize {
+    let total: u32 = options.iter().map(|option| option.weight).sum();
+    let mut target = random % total;
+    for (index, option) in options.iter().enumerate() {
+        if target < option.weight {
+            return index;
+        }
+        target -= option.weight;
+    }
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(weights: &[u32]) -> Vec<TraitOption> {
+        weights
+            .iter()
+            .map(|weight| TraitOption {
+                value: weight.to_string(),
+                weight: *weight,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pick_respects_weights() {
+        let options = options(&[1, 3, 6]);
+        let mut counts = [0; 3];
+        for random in 0..1_000 {
+            counts[pick_weighted(&options, random)] += 1;
+        }
+        assert_eq!(counts, [100, 300, 600]);
+    }
+
+    #[test]
+    fn draw_depends_on_token_id() {
+        let mut table = TraitTable::new(vec![TraitCategory {
+            name: "background".to_string(),
+            options: options(&[1; 16]),
+        }]);
+        let traits: std::collections::HashSet<String> = (0..10)
+            .map(|id| table.draw(&id.to_string()).unwrap())
+            .collect();
+        assert!(traits.len() > 1);
+        let counted: u32 = table.distribution()[0]
+            .options
+            .iter()
+            .map(|o| o.count)
+            .sum();
+        assert_eq!(counted, 10);
+    }
+}
@@ -58,6 +58,10 @@ impl Allowance {
         self.used += num
     }
 
+    pub fn return_num(&mut self, num: u16) {
+        self.used = self.used.saturating_sub(num);
+    }
+
     pub fn increase_max(&mut self, num: u16) {
         self.max += num;
     }
@@ -68,7 +72,7 @@ impl Allowance {
     }
 }
 
-#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize)]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 #[witgen]
 pub struct Sale {
@@ -99,6 +103,28 @@ impl Default for Sale {
 }
 
 impl Sale {
+    /// Status of the sale when `tokens_left` can still be minted
+    pub fn status(&self, tokens_left: u32) -> Status {
+        if tokens_left == 0 {
+            return Status::SoldOut;
+        }
+        let current_time = current_time_ms();
+        match (self.presale_start, self.public_sale_start) {
+            (_, Some(public)) if public < current_time => Status::Open,
+            (Some(pre), _) if pre < current_time => Status::Presale,
+            (_, _) => Status::Closed,
+        }
+    }
+
+    /// Price of one token while the sale has `status`
+    pub fn current_price(&self, status: &Status) -> u128 {
+        match status {
+            Status::Presale | Status::Closed => self.presale_price.unwrap_or(self.price),
+            Status::Open | Status::SoldOut => self.price,
+        }
+        .into()
+    }
+
     pub fn validate(&self) {
         if let Some(r) = self.royalties.as_ref() {
             r.validate()
@@ -107,7 +133,436 @@ impl Sale {
             r.validate()
         }
     }
+
+    /// Checks that the sale dates and prices make sense together
+    pub fn validate_schedule(&self) {
+        if let (Some(presale), Some(public)) = (self.presale_start, self.public_sale_start) {
+            require!(
+                presale <= public,
+                "presale must not start after the public sale"
+            );
+        }
+        if let Some(presale_price) = self.presale_price {
+            require!(
+                presale_price.0 <= self.price.0,
+                "presale price must not be higher than the public price"
+            );
+        }
+        if let Some(limit) = self.mint_rate_limit {
+            require!(limit > 0, "mint rate limit must be greater than zero");
+        }
+    }
+
+    /// Apply the fields that are set in `patch`
+    pub fn apply(&mut self, patch: SalePatch) {
+        let SalePatch {
+            royalties,
+            initial_royalties,
+            presale_start,
+            public_sale_start,
+            allowance,
+            presale_price,
+            price,
+            mint_rate_limit,
+        } = patch;
+        if let Some(royalties) = royalties {
+            self.royalties = royalties;
+        }
+        if let Some(initial_royalties) = initial_royalties {
+            self.initial_royalties = initial_royalties;
+        }
+        if let Some(presale_start) = presale_start {
+            self.presale_start = presale_start;
+        }
+        if let Some(public_sale_start) = public_sale_start {
+            self.public_sale_start = public_sale_start;
+        }
+        if let Some(allowance) = allowance {
+            self.allowance = allowance;
+        }
+        if let Some(presale_price) = presale_price {
+            self.presale_price = presale_price;
+        }
+        if let Some(price) = price {
+            self.price = price;
+        }
+        if let Some(mint_rate_limit) = mint_rate_limit {
+            self.mint_rate_limit = mint_rate_limit;
+        }
+    }
+}
+
+/// Partial update of a `Sale`. Missing fields are left unchanged,
+/// while `null` clears an optional field.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct SalePatch {
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub royalties: Option<Option<Royalties>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub initial_royalties: Option<Option<Royalties>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub presale_start: Option<Option<TimestampMs>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub public_sale_start: Option<Option<TimestampMs>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub allowance: Option<Option<u16>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub presale_price: Option<Option<U128>>,
+    #[serde(default)]
+    pub price: Option<U128>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub mint_rate_limit: Option<Option<u16>>,
+}
+/// Partial update of `NFTContractMetadata`. Missing fields are left unchanged,
+/// while `null` clears an optional field.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct ContractMetadataPatch {
+    #[serde(default)]
+    pub spec: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub symbol: Option<String>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub icon: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub base_uri: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub reference: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub reference_hash: Option<Option<Base64VecU8>>,
+}
+
+impl ContractMetadataPatch {
+    pub fn apply(self, metadata: &mut NFTContractMetadata) {
+        let ContractMetadataPatch {
+            spec,
+            name,
+            symbol,
+            icon,
+            base_uri,
+            reference,
+            reference_hash,
+        } = self;
+        if let Some(spec) = spec {
+            metadata.spec = spec;
+        }
+        if let Some(name) = name {
+            metadata.name = name;
+        }
+        if let Some(symbol) = symbol {
+            metadata.symbol = symbol;
+        }
+        if let Some(icon) = icon {
+            metadata.icon = icon;
+        }
+        if let Some(base_uri) = base_uri {
+            metadata.base_uri = base_uri;
+        }
+        if let Some(reference) = reference {
+            metadata.reference = reference;
+        }
+        if let Some(reference_hash) = reference_hash {
+            metadata.reference_hash = reference_hash;
+        }
+    }
+}
+
+/// Templates used to build the metadata of each minted token.
+/// `{id}`, `{name}` and `{ext}` are replaced by the token id, the collection name
+/// and the media extension. A field set to `None` is left empty.
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct MetadataTemplate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub reference: Option<String>,
+    pub extra: Option<String>,
+}
+
+impl Default for MetadataTemplate {
+    fn default() -> Self {
+        Self {
+            title: Some("{name} #{id}".to_string()),
+            description: None,
+            media: Some("{id}.{ext}".to_string()),
+            reference: Some("{id}.json".to_string()),
+            extra: None,
+        }
+    }
+}
+
+impl MetadataTemplate {
+    pub fn fill(field: &Option<String>, id: &str, name: &str, ext: &str) -> Option<String> {
+        field.as_ref().map(|template| {
+            template
+                .replace("{id}", id)
+                .replace("{name}", name)
+                .replace("{ext}", ext)
+        })
+    }
+
+    /// Metadata of a newly minted token
+    pub fn render(&self, id: &str, name: &str, ext: &str) -> TokenMetadata {
+        let fill = |field: &Option<String>| Self::fill(field, id, name, ext);
+        TokenMetadata {
+            title: fill(&self.title), // ex. "Arch Nemesis: Mail Carrier" or "Parcel #5055"
+            media: fill(&self.media), // URL to associated media, preferably to decentralized, content-addressed storage
+            issued_at: Some(current_time_ms().to_string()), // ISO 8601 datetime when token was issued or minted
+            reference: fill(&self.reference), // URL to an off-chain JSON file with more info.
+            description: fill(&self.description), // free-form description
+            media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
+            copies: None, // number of copies of this set of metadata in existence when token was minted.
+            expires_at: None, // ISO 8601 datetime when token expires
+            starts_at: None, // ISO 8601 datetime when token starts being valid
+            updated_at: None, // ISO 8601 datetime when token was last updated
+            extra: fill(&self.extra), // anything extra the NFT wants to store on-chain. Can be stringified JSON.
+            reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+        }
+    }
+}
+
+/// How raffle indexes are turned into token ids, e.g. `{ offset: 1, prefix: "NGO-", pad_width: 4 }`
+/// turns index `0` into `NGO-0001`.
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TokenIdFormat {
+    /// Added to the raffle index
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Minimum number of digits, padded with leading zeros
+    #[serde(default)]
+    pub pad_width: u8,
+}
+
+impl TokenIdFormat {
+    pub fn validate(&self) {
+        require!(self.pad_width <= 20, "pad width can be at most 20 digits");
+        if let Some(prefix) = self.prefix.as_ref() {
+            require!(
+                !prefix.ends_with(|c: char| c.is_ascii_digit()),
+                "token id prefix must not end with a digit"
+            );
+            require!(
+                !prefix.contains(SERIES_SEPARATOR),
+                "token id prefix can't contain ':', it separates series ids"
+            );
+        }
+    }
+
+    pub fn format(&self, index: u64) -> TokenId {
+        let number = index
+            .checked_add(self.offset)
+            .unwrap_or_else(|| env::panic_str("token id overflow"));
+        format!(
+            "{}{:0width$}",
+            self.prefix.as_deref().unwrap_or(""),
+            number,
+            width = self.pad_width as usize
+        )
+    }
+
+    /// Raffle index of `token_id`, if it could have been produced by `format`
+    pub fn parse(&self, token_id: &str) -> Option<u64> {
+        let number = token_id
+            .strip_prefix(self.prefix.as_deref().unwrap_or(""))?
+            .parse::<u64>()
+            .ok()?;
+        let index = number.checked_sub(self.offset)?;
+        if self.format(index) == token_id {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// Base URI used for the tokens with raffle indexes in `start..end`,
+/// for collections uploaded in several chunks with different CIDs.
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct BaseUriRange {
+    pub start: u64,
+    /// Exclusive
+    pub end: u64,
+    pub base_uri: String,
+}
+
+impl BaseUriRange {
+    pub fn contains(&self, index: u64) -> bool {
+        self.start <= index && index < self.end
+    }
+
+    pub fn overlaps(&self, other: &BaseUriRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Metadata loaded ahead of time for a single token.
+/// Fields that are set take precedence over the ones generated from the `MetadataTemplate`.
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TokenMetadataOverride {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media_hash: Option<Base64VecU8>,
+    pub reference_hash: Option<Base64VecU8>,
+    pub extra: Option<String>,
+}
+
+impl TokenMetadataOverride {
+    pub fn validate(&self) {
+        for hash in [&self.media_hash, &self.reference_hash]
+            .iter()
+            .copied()
+            .flatten()
+        {
+            require!(hash.0.len() == 32, "Hash has to be 32 bytes");
+        }
+    }
+
+    pub fn apply(self, metadata: &mut TokenMetadata) {
+        let TokenMetadataOverride {
+            title,
+            description,
+            media_hash,
+            reference_hash,
+            extra,
+        } = self;
+        if title.is_some() {
+            metadata.title = title;
+        }
+        if description.is_some() {
+            metadata.description = description;
+        }
+        if media_hash.is_some() {
+            metadata.media_hash = media_hash;
+        }
+        if reference_hash.is_some() {
+            metadata.reference_hash = reference_hash;
+        }
+        if extra.is_some() {
+            metadata.extra = extra;
+        }
+    }
+}
+
+/// Partial update of a minted token's metadata. Missing fields are left unchanged,
+/// while `null` clears the field.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[witgen]
+pub struct TokenMetadataPatch {
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub title: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub description: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub media: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub media_hash: Option<Option<Base64VecU8>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub reference: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub reference_hash: Option<Option<Base64VecU8>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub starts_at: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub expires_at: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub extra: Option<Option<String>>,
+}
+
+impl TokenMetadataPatch {
+    pub fn validate(&self) {
+        for hash in [&self.media_hash, &self.reference_hash]
+            .iter()
+            .copied()
+            .flatten()
+            .flatten()
+        {
+            require!(hash.0.len() == 32, "Hash has to be 32 bytes");
+        }
+    }
+
+    pub fn apply(&self, metadata: &mut TokenMetadata) {
+        fn set<T: Clone>(field: &mut Option<T>, value: &Option<Option<T>>) {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        set(&mut metadata.title, &self.title);
+        set(&mut metadata.description, &self.description);
+        set(&mut metadata.media, &self.media);
+        set(&mut metadata.media_hash, &self.media_hash);
+        set(&mut metadata.reference, &self.reference);
+        set(&mut metadata.reference_hash, &self.reference_hash);
+        set(&mut metadata.starts_at, &self.starts_at);
+        set(&mut metadata.expires_at, &self.expires_at);
+        set(&mut metadata.extra, &self.extra);
+    }
+}
+
+/// How much of the collection has metadata loaded with `load_token_metadata`
+#[witgen]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataLoadProgress {
+    /// Number of tokens with loaded metadata
+    pub loaded: u32,
+    /// Total tokens that could be minted
+    pub total: u64,
+}
+
+/// Where `airdrop_mint` stopped: the recipient it was minting to
+/// and how many of their tokens were already minted
+#[witgen]
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AirdropCursor {
+    pub recipient: u32,
+    pub minted: u16,
+}
+
+/// Order in which tokens are handed out when minting
+#[witgen]
+#[derive(Deserialize, Serialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MintOrder {
+    /// Each token is randomly drawn from the ones left
+    Random,
+    /// Tokens are handed out in ascending order
+    Sequential,
+}
+
+impl Default for MintOrder {
+    fn default() -> Self {
+        MintOrder::Random
+    }
 }
+
+/// Which tokens `reduce_supply` removes from the pool
+#[witgen]
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SupplyReduction {
+    /// Randomly drawn tokens, like minting them
+    Random,
+    /// The highest ids left. Only possible until a token is drawn randomly.
+    Highest,
+}
+
 /// Current state of contract
 #[witgen]
 #[derive(Serialize)]
@@ -173,14 +628,16 @@ mod tests {
     }
 
     fn new_contract() -> Contract {
-        Contract::new_default_meta(
+        Contract::new(
             AccountId::new_unchecked("root".to_string()),
-            initial_metadata(),
+            initial_metadata().into(),
             10_000,
-            Some(Sale {
+            Sale {
                 price: TEN.into(),
                 ..Default::default()
-            }),
+            },
+            None,
+            None,
             None,
         )
     }
@@ -190,4 +647,85 @@ mod tests {
         let contract = new_contract();
         assert_eq!(contract.cost_per_token(&account()).0, TEN);
     }
+
+    #[test]
+    fn fill_metadata_template() {
+        let template = MetadataTemplate {
+            description: Some("Token {id} of {name}".to_string()),
+            ..Default::default()
+        };
+        let fill = |field| MetadataTemplate::fill(field, "42", "Gotchi", "gif");
+        assert_eq!(fill(&template.title).unwrap(), "Gotchi #42");
+        assert_eq!(fill(&template.description).unwrap(), "Token 42 of Gotchi");
+        assert_eq!(fill(&template.media).unwrap(), "42.gif");
+        assert_eq!(fill(&template.reference).unwrap(), "42.json");
+        assert!(fill(&template.extra).is_none());
+    }
+
+    #[test]
+    fn token_id_format_round_trip() {
+        let format = TokenIdFormat {
+            offset: 1,
+            prefix: Some("NGO-".to_string()),
+            pad_width: 4,
+        };
+        assert_eq!(format.format(0), "NGO-0001");
+        assert_eq!(format.format(99_999), "NGO-100000");
+        assert_eq!(format.parse("NGO-0001"), Some(0));
+        assert_eq!(format.parse("NGO-100000"), Some(99_999));
+        assert_eq!(format.parse("NGO-1"), None);
+        assert_eq!(format.parse("NGO-0000"), None);
+        assert_eq!(format.parse("0001"), None);
+        assert_eq!(TokenIdFormat::default().parse("42"), Some(42));
+    }
+
+    #[test]
+    #[should_panic(expected = "token id prefix can't contain ':'")]
+    fn token_id_prefix_without_series_separator() {
+        TokenIdFormat {
+            prefix: Some("a:".to_string()),
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    fn base_uri_ranges_overlap() {
+        let range = |start, end| BaseUriRange {
+            start,
+            end,
+            base_uri: "ipfs://".to_string(),
+        };
+        assert!(range(0, 10).contains(9));
+        assert!(!range(0, 10).contains(10));
+        assert!(range(0, 10).overlaps(&range(9, 20)));
+        assert!(!range(0, 10).overlaps(&range(10, 20)));
+    }
+
+    #[test]
+    fn apply_sale_patch() {
+        let mut sale = Sale {
+            price: TEN.into(),
+            presale_price: Some(TEN.into()),
+            ..Default::default()
+        };
+        let patch: SalePatch =
+            near_sdk::serde_json::from_str(r#"{"presale_price": null, "allowance": 3}"#).unwrap();
+        sale.apply(patch);
+        assert_eq!(sale.price.0, TEN);
+        assert!(sale.presale_price.is_none());
+        assert_eq!(sale.allowance, Some(3));
+        assert_eq!(sale.mint_rate_limit, Some(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "presale must not start after the public sale")]
+    fn sale_schedule_out_of_order() {
+        Sale {
+            presale_start: Some(2),
+            public_sale_start: Some(1),
+            ..Default::default()
+        }
+        .validate_schedule();
+    }
 }
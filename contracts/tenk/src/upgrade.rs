@@ -0,0 +1,374 @@
+use crate::*;
+use near_sdk::IntoStorageKey;
+
+/// Version of the layout `Contract` is stored with. Bump it, and freeze the previous layout as a
+/// variant of `VersionedContract`, whenever a release changes the fields of `Contract`.
+pub(crate) const CONTRACT_VERSION: u16 = 2;
+
+const GAS_FOR_UPGRADE_CALL: Gas = Gas(parse_gas!("10 Tgas") as u64);
+
+/// Layouts the contract state has had on chain.
+/// The first deployed version was stored without a tag, so the version is tracked under
+/// its own storage key and `V1` is assumed when that key is missing.
+pub enum VersionedContract {
+    V1(ContractV1),
+    /// The current layout
+    V2(Contract),
+}
+
+impl VersionedContract {
+    pub(crate) fn read() -> Self {
+        let state =
+            env::storage_read(b"STATE").unwrap_or_else(|| env::panic_str("No state to migrate"));
+        match stored_version() {
+            1 => Self::V1(ContractV1::try_from_slice(&state).expect("Cannot read V1 state")),
+            2 => Self::V2(Contract::try_from_slice(&state).expect("Cannot read V2 state")),
+            version => env::panic_str(&format!("Unknown contract version {}", version)),
+        }
+    }
+}
+
+impl From<VersionedContract> for Contract {
+    fn from(versioned: VersionedContract) -> Self {
+        match versioned {
+            VersionedContract::V1(contract) => contract.into(),
+            VersionedContract::V2(contract) => contract,
+        }
+    }
+}
+
+/// State as deployed before versioning was introduced
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    raffle: RaffleV1,
+    pending_tokens: u32,
+    accounts: LookupMap<PublicKey, bool>,
+    whitelist: LookupMap<AccountId, Allowance>,
+    sale: SaleV1,
+    admins: UnorderedSet<AccountId>,
+    media_extension: Option<String>,
+}
+
+impl From<ContractV1> for Contract {
+    fn from(old: ContractV1) -> Self {
+        let ContractV1 {
+            tokens,
+            metadata,
+            raffle,
+            pending_tokens,
+            accounts,
+            whitelist,
+            sale,
+            admins,
+            media_extension,
+        } = old;
+        // Nothing could be removed from the raffle yet, so every value not left was minted
+        let raffle = Raffle::restore(
+            raffle.prefix,
+            raffle.len,
+            raffle.len + tokens.owner_by_id.len(),
+        );
+        Self {
+            tokens,
+            metadata,
+            raffle,
+            pending_tokens,
+            accounts,
+            whitelist,
+            sale: sale.into(),
+            admins,
+            media_extension,
+            metadata_frozen: false,
+            metadata_template: MetadataTemplate::default(),
+            token_metadata: LookupMap::new(StorageKey::TokenMetadataOverrides),
+            token_metadata_loaded: 0,
+            // V1 hardcoded the NEARGotchi animal types
+            traits: TraitTable::animal_types(),
+            metadata_updaters: UnorderedSet::new(StorageKey::MetadataUpdaters),
+            token_id_format: TokenIdFormat::default(),
+            series: UnorderedMap::new(StorageKey::Series),
+            series_whitelist: LookupMap::new(StorageKey::SeriesWhitelist),
+            series_minted: 0,
+            base_uri_ranges: vec![],
+            draw_nonce: 0,
+            mint_order: MintOrder::default(),
+            // Tokens minted before the upgrade were never recorded in order,
+            // `backfill_minted_tokens` adds them
+            minted_tokens: Vector::new(StorageKey::MintedTokens),
+            lottery: None,
+            linkdrops: LookupMap::new(StorageKey::Linkdrops),
+            pending_linkdrops: UnorderedSet::new(StorageKey::PendingLinkdrops),
+            linkdrops_by_creator: LookupMap::new(StorageKey::LinkdropsByCreatorIndex),
+            removed_tokens: LookupSet::new(StorageKey::RemovedTokens),
+            linkdrop_fee: DEFAULT_LINKDROP_FEE,
+            linkdrop_factory: default_linkdrop_factory(),
+            linkdrop_claim_deposit: LINKDROP_DEPOSIT,
+            minted_tokens_backfilled: 0,
+        }
+    }
+}
+
+/// `Raffle` as stored by `ContractV1`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RaffleV1 {
+    len: u64,
+    prefix: Vec<u8>,
+}
+
+/// `Sale` as stored by `ContractV1`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SaleV1 {
+    royalties: Option<Royalties>,
+    initial_royalties: Option<Royalties>,
+    presale_start: Option<TimestampMs>,
+    public_sale_start: Option<TimestampMs>,
+    allowance: Option<u16>,
+    presale_price: Option<U128>,
+    price: U128,
+    mint_rate_limit: Option<u16>,
+}
+
+impl From<SaleV1> for Sale {
+    fn from(old: SaleV1) -> Self {
+        let SaleV1 {
+            royalties,
+            initial_royalties,
+            presale_start,
+            public_sale_start,
+            allowance,
+            presale_price,
+            price,
+            mint_rate_limit,
+        } = old;
+        Self {
+            royalties,
+            initial_royalties,
+            presale_start,
+            public_sale_start,
+            allowance,
+            presale_price,
+            price,
+            mint_rate_limit,
+        }
+    }
+}
+
+fn version_key() -> Vec<u8> {
+    StorageKey::Version.into_storage_key()
+}
+
+/// Version of the stored state. Missing key means the state predates versioning.
+pub(crate) fn stored_version() -> u16 {
+    env::storage_read(&version_key())
+        .map(|bytes| u16::try_from_slice(&bytes).expect("Cannot deserialize contract version"))
+        .unwrap_or(1)
+}
+
+pub(crate) fn write_version() {
+    env::storage_write(&version_key(), &CONTRACT_VERSION.try_to_vec().unwrap());
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deploy new code to this account and call `migrate` on it.
+    /// The wasm is passed as the raw input of the call.
+    /// @allow ["::owner"]
+    pub fn upgrade(&self) -> Promise {
+        self.assert_owner();
+        let code = env::input().unwrap_or_else(|| env::panic_str("No code attached"));
+        let gas = env::prepaid_gas()
+            .0
+            .checked_sub(env::used_gas().0)
+            .and_then(|gas| gas.checked_sub(GAS_FOR_UPGRADE_CALL.0))
+            .unwrap_or_else(|| env::panic_str("Not enough gas attached to deploy and migrate"));
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], 0, Gas(gas))
+    }
+
+    /// Convert any older state into the current layout.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let contract: Contract = VersionedContract::read().into();
+        write_version();
+        contract
+    }
+
+    /// Version of the state layout currently stored
+    pub fn contract_version(&self) -> u16 {
+        stored_version()
+    }
+
+    /// Add up to `limit` tokens minted before the upgrade to the mint order. Their mint order
+    /// was never recorded, so they are added in token id order. Tokens held by this contract,
+    /// like those escrowed for linkdrops, are skipped. Only possible until a token is minted
+    /// after the upgrade. Returns the number added, 0 once all are.
+    /// @allow ["::owner"]
+    pub fn backfill_minted_tokens(&mut self, limit: u32) -> u32 {
+        self.assert_owner();
+        require!(
+            self.minted_tokens.len() == self.minted_tokens_backfilled,
+            "Tokens were minted since the upgrade"
+        );
+        let last = self
+            .minted_tokens
+            .len()
+            .checked_sub(1)
+            .and_then(|index| self.minted_tokens.get(index))
+            .unwrap_or_default();
+        let contract_id = env::current_account_id();
+        let token_ids: Vec<TokenId> = self
+            .tokens
+            .owner_by_id
+            .iter_from(last)
+            .filter(|(_, owner_id)| owner_id != &contract_id)
+            .map(|(token_id, _)| token_id)
+            .take(limit as usize)
+            .collect();
+        token_ids
+            .iter()
+            .for_each(|token_id| self.minted_tokens.push(token_id));
+        self.minted_tokens_backfilled += token_ids.len() as u64;
+        token_ids.len() as u32
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{contract_metadata, new_contract};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn context() -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0));
+        context
+    }
+
+    /// Store the state of a contract deployed before versioning with `minted` of `size` tokens minted
+    fn write_v1_state(size: u64, minted: u64) {
+        let mut tokens = NonFungibleToken::new(
+            StorageKey::NonFungibleToken,
+            accounts(0),
+            Some(StorageKey::TokenMetadata),
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+        );
+        (0..minted).for_each(|index| {
+            let token_id = (size - 1 - index).to_string();
+            let metadata = MetadataTemplate::default().render(&token_id, "name", "png");
+            tokens.internal_mint_with_refund(token_id, accounts(1), Some(metadata), None);
+        });
+        let contract = ContractV1 {
+            tokens,
+            metadata: LazyOption::new(StorageKey::Metadata, Some(&contract_metadata())),
+            raffle: RaffleV1 {
+                len: size - minted,
+                prefix: StorageKey::Raffle.into_storage_key(),
+            },
+            pending_tokens: 0,
+            accounts: LookupMap::new(StorageKey::LinkdropKeys),
+            whitelist: LookupMap::new(StorageKey::Whitelist),
+            sale: SaleV1 {
+                royalties: None,
+                initial_royalties: None,
+                presale_start: None,
+                public_sale_start: None,
+                allowance: None,
+                presale_price: None,
+                price: U128(0),
+                mint_rate_limit: None,
+            },
+            admins: UnorderedSet::new(StorageKey::Admins),
+            media_extension: None,
+        };
+        env::storage_write(b"STATE", &contract.try_to_vec().unwrap());
+    }
+
+    #[test]
+    fn migrate_from_v1() {
+        testing_env!(context().build());
+        write_v1_state(10, 3);
+        assert_eq!(stored_version(), 1);
+        let contract = Contract::migrate();
+        assert_eq!(contract.contract_version(), CONTRACT_VERSION);
+        assert_eq!(contract.raffle.len(), 7);
+        assert_eq!(contract.raffle.total(), 10);
+        assert_eq!(contract.tokens_left(), 7);
+        assert_eq!(contract.nft_total_supply().0, 3);
+    }
+
+    #[test]
+    fn migrated_tokens_get_animal_type() {
+        testing_env!(context().build());
+        write_v1_state(10, 3);
+        let mut contract = Contract::migrate();
+        contract.airdrop_mint(vec![(accounts(2), 1)], None);
+        let token = contract.nft_token_by_mint_index(0).unwrap();
+        let extra = token.metadata.unwrap().extra.unwrap();
+        assert!(["1", "2", "3"].contains(&extra.as_str()));
+    }
+
+    #[test]
+    fn backfill_tokens_minted_before_upgrade() {
+        testing_env!(context().build());
+        write_v1_state(10, 4);
+        let mut contract = Contract::migrate();
+        let escrowed = "8".to_string();
+        contract
+            .tokens
+            .internal_transfer(&accounts(1), &accounts(5), &escrowed, None, None);
+        assert_eq!(contract.backfill_minted_tokens(2), 2);
+        assert_eq!(contract.backfill_minted_tokens(2), 1);
+        assert_eq!(contract.backfill_minted_tokens(2), 0);
+        let backfilled: Vec<TokenId> = (0..contract.nft_mint_index_len())
+            .filter_map(|index| contract.token_minted_at(index))
+            .map(|(token_id, _)| token_id)
+            .collect();
+        assert_eq!(backfilled, vec!["6", "7", "9"]);
+
+        contract.airdrop_mint(vec![(accounts(2), 1)], None);
+        assert_eq!(contract.nft_mint_index_len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tokens were minted since the upgrade")]
+    fn backfill_before_minting() {
+        testing_env!(context().build());
+        write_v1_state(10, 2);
+        let mut contract = Contract::migrate();
+        contract.airdrop_mint(vec![(accounts(2), 1)], None);
+        contract.backfill_minted_tokens(10);
+    }
+
+    #[test]
+    fn migrate_current_state() {
+        testing_env!(context().build());
+        let mut contract = new_contract(Sale::default());
+        contract.reduce_supply(2, SupplyReduction::Random);
+        env::storage_write(b"STATE", &contract.try_to_vec().unwrap());
+        let migrated = Contract::migrate();
+        assert_eq!(migrated.tokens_left(), 8);
+        assert_eq!(migrated.contract_version(), CONTRACT_VERSION);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough gas attached to deploy and migrate")]
+    fn upgrade_needs_gas() {
+        testing_env!(context().build());
+        let contract = new_contract(Sale::default());
+        testing_env!(context()
+            .input(vec![0])
+            .prepaid_gas(Gas(parse_gas!("5 Tgas") as u64))
+            .build());
+        contract.upgrade();
+    }
+}
@@ -1,5 +1,7 @@
-use near_contract_standards::non_fungible_token::{events::NftMint, Token};
-use near_sdk::{env, AccountId, Promise, PromiseResult};
+use near_contract_standards::non_fungible_token::{
+    events::NftMint, metadata::TokenMetadata, Token, TokenId,
+};
+use near_sdk::{env, serde_json, AccountId, Promise, PromiseResult};
 
 use crate::TimestampMs;
 pub fn is_promise_success(num_of_promises: Option<u64>) -> bool {
@@ -16,13 +18,21 @@ pub fn is_promise_success(num_of_promises: Option<u64>) -> bool {
     true
 }
 
-pub fn get_random_number(shift_amount: u32) -> u32 {
-    let mut seed = env::random_seed();
-    let seed_len = seed.len();
-    let mut arr: [u8; 4] = Default::default();
-    seed.rotate_left(shift_amount as usize % seed_len);
-    arr.copy_from_slice(&seed[..4]);
-    u32::from_le_bytes(arr)
+/// Randomness for a single draw. The block's random seed is the same for every draw in a
+/// transaction, so it is hashed with a per-contract counter, the minter and the index of the draw.
+pub fn random_u64(nonce: u64, minter: &AccountId, index: u64) -> u64 {
+    let hash = env::sha256(
+        &[
+            &env::random_seed()[..],
+            &nonce.to_le_bytes(),
+            minter.as_bytes(),
+            &index.to_le_bytes(),
+        ]
+        .concat(),
+    );
+    let mut arr: [u8; 8] = Default::default();
+    arr.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(arr)
 }
 
 pub fn refund(account_id: &AccountId, amount: u128) -> Option<Promise> {
@@ -36,6 +46,13 @@ pub fn current_time_ms() -> TimestampMs {
     env::block_timestamp() / 1_000_000
 }
 
+/// Make `media` and `reference` absolute URLs under `base_uri`
+pub fn set_base_uri(metadata: &mut TokenMetadata, base_uri: &str) {
+    let join = |path: String| format!("{}/{}", base_uri.trim_end_matches('/'), path);
+    metadata.media = metadata.media.take().map(join);
+    metadata.reference = metadata.reference.take().map(join);
+}
+
 pub fn log_mint(owner_id: &AccountId, tokens: &[Token]) {
     let token_ids = &tokens
         .iter()
@@ -48,3 +65,28 @@ pub fn log_mint(owner_id: &AccountId, tokens: &[Token]) {
     }
     .emit()
 }
+
+/// Emit a NEP-171 event that `near_contract_standards` doesn't provide
+pub fn log_nep171_event(event: &str, version: &str, data: serde_json::Value) {
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        serde_json::json!({
+            "standard": "nep171",
+            "version": version,
+            "event": event,
+            "data": data,
+        })
+    ));
+}
+
+pub fn log_contract_metadata_update() {
+    log_nep171_event("contract_metadata_update", "1.1.0", serde_json::json!([{}]));
+}
+
+pub fn log_nft_metadata_update(token_ids: &[TokenId]) {
+    log_nep171_event(
+        "nft_metadata_update",
+        "1.1.0",
+        serde_json::json!([{ "token_ids": token_ids }]),
+    );
+}
@@ -12,6 +12,11 @@ impl Contract {
         self.admins.to_vec()
     }
 
+    /// Accounts besides the owner and admins allowed to update token metadata
+    pub fn metadata_updaters(&self) -> Vec<AccountId> {
+        self.metadata_updaters.to_vec()
+    }
+
     /// Check whether an account is allowed to mint during the presale
     pub fn whitelisted(&self, account_id: &AccountId) -> bool {
         self.whitelist.contains_key(account_id)
@@ -23,6 +28,21 @@ impl Contract {
             .into()
     }
 
+    /// Cost of a linkdrop for a token the minter already owns
+    pub fn cost_of_token_linkdrop(&self, minter: &AccountId) -> U128 {
+        self.full_link_price(minter).into()
+    }
+
+    /// Fee charged on top of the token price for each linkdrop token
+    pub fn linkdrop_fee(&self) -> U128 {
+        self.linkdrop_fee.into()
+    }
+
+    /// NEAR sent to the account claiming a linkdrop created now
+    pub fn linkdrop_claim_deposit(&self) -> U128 {
+        self.linkdrop_claim_deposit.into()
+    }
+
     pub fn total_cost(&self, num: u16, minter: &AccountId) -> U128 {
         (num as Balance * self.cost_per_token(minter).0).into()
     }
@@ -52,6 +72,34 @@ impl Contract {
         self.metadata.get().unwrap()
     }
 
+    /// Templates used to build the metadata of each minted token
+    pub fn metadata_template(&self) -> MetadataTemplate {
+        self.metadata_template.clone()
+    }
+
+    /// Traits drawn for each token and the weight of each option
+    pub fn traits(&self) -> Vec<TraitCategory> {
+        self.traits.categories()
+    }
+
+    /// How many minted tokens got each option of each trait
+    pub fn trait_distribution(&self) -> Vec<TraitDistribution> {
+        self.traits.distribution()
+    }
+
+    /// How many tokens have had their metadata loaded
+    pub fn token_metadata_progress(&self) -> MetadataLoadProgress {
+        MetadataLoadProgress {
+            loaded: self.token_metadata_loaded,
+            total: self.initial(),
+        }
+    }
+
+    /// Whether the contract metadata is locked and can no longer be changed
+    pub fn is_metadata_frozen(&self) -> bool {
+        self.metadata_frozen
+    }
+
     /// How many tokens an account is still allowed to mint. None, means unlimited
     pub fn remaining_allowance(&self, account_id: &AccountId, new_max: Option<u16>) -> Option<u16> {
         self.whitelist
@@ -75,6 +123,11 @@ impl Contract {
         }
     }
 
+    /// Raw configuration of the current sale
+    pub fn get_sale(&self) -> Sale {
+        self.sale.clone()
+    }
+
     /// Information about a current user. Whether they are VIP and how many tokens left in their allowance.
     pub fn get_user_sale_info(&self, account_id: &AccountId) -> UserSaleInfo {
         let sale_info = self.get_sale_info();
@@ -92,8 +145,49 @@ impl Contract {
         }
     }
 
-    /// Initial size of collection. Number left to raffle + current total supply
+    /// Base URIs of token ranges uploaded separately from the contract's `base_uri`
+    pub fn base_uri_ranges(&self) -> Vec<BaseUriRange> {
+        self.base_uri_ranges.clone()
+    }
+
+    /// Whether tokens are drawn randomly or handed out in order
+    pub fn mint_order(&self) -> MintOrder {
+        self.mint_order
+    }
+
+    /// How raffle indexes are turned into token ids
+    pub fn token_id_format(&self) -> TokenIdFormat {
+        self.token_id_format.clone()
+    }
+
+    /// Raffle index a token id was drawn as. None if the token id isn't part of this collection.
+    pub fn raffle_index(&self, token_id: TokenId) -> Option<u64> {
+        self.token_id_format
+            .parse(&token_id)
+            .filter(|index| *index < self.raffle.total())
+    }
+
+    /// The `index`th token minted, counting from the first mint after the index was added.
+    /// Tokens minted before it come first once added with `backfill_minted_tokens`.
+    pub fn nft_token_by_mint_index(&self, index: u64) -> Option<Token> {
+        self.minted_tokens
+            .get(index)
+            .and_then(|token_id| self.nft_token(token_id))
+    }
+
+    /// Number of tokens recorded in mint order
+    pub fn nft_mint_index_len(&self) -> u64 {
+        self.minted_tokens.len()
+    }
+
+    /// Whether `reduce_supply` can still remove the highest ids left. Only until a token is drawn
+    /// randomly, e.g. by a sale, after which only `Random` reductions are possible.
+    pub fn can_reduce_highest_supply(&self) -> bool {
+        self.raffle.is_ordered()
+    }
+
+    /// Initial size of collection. Number left to raffle + current total supply, not counting series
     pub fn initial(&self) -> u64 {
-        self.raffle.len() + self.nft_total_supply().0 as u64
+        self.raffle.len() + self.nft_total_supply().0 as u64 - self.series_minted
     }
 }